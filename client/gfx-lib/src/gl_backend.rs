@@ -0,0 +1,294 @@
+use std::mem;
+use std::ptr;
+use std::ffi::CStr;
+use glutin::{self, GlContext};
+use gl;
+use gl::types::*;
+use image::RgbaImage;
+use nalgebra::{self, Isometry3, Matrix4, Orthographic3, Vector3};
+use {Backend, Sprite, CELL_WIDTH, CELL_HEIGHT, load_font, glyph_position};
+
+const VS_SRC: &'static [u8] = b"
+#version 150 core
+
+uniform mat4 modelViewProjection;
+
+in vec2 position;
+in vec3 color;
+in vec2 uv;
+
+out vec3 Color;
+out vec2 TexCoord;
+
+void main()
+{
+    Color = color;
+    TexCoord = uv;
+    gl_Position = modelViewProjection * vec4(position, 0.0, 1.0);
+}
+\0";
+
+const FS_SRC: &'static [u8] = b"
+#version 150 core
+
+uniform sampler2D tex;
+
+in vec3 Color;
+in vec2 TexCoord;
+
+out vec4 outColor;
+
+void main()
+{
+    outColor = texture(tex, TexCoord) * vec4(Color, 1.0);
+}
+\0";
+
+pub struct Window {
+    pub events_loop: glutin::EventsLoop,
+    pub gl_window: glutin::GlWindow,
+    pub width: u32,
+    pub height: u32,
+    pub is_close_requested: bool
+}
+
+impl Window {
+    pub fn new(title: &str, width: u32, height: u32) -> Window {
+        let window = glutin::WindowBuilder::new()
+            .with_title(title)
+            .with_dimensions(width, height)
+            .with_min_dimensions(width, height)
+            .with_max_dimensions(width, height);
+
+        let context = glutin::ContextBuilder::new()
+            .with_vsync(true);
+
+        let events_loop = glutin::EventsLoop::new();
+        let gl_window = glutin::GlWindow::new(window, context, &events_loop).unwrap();
+
+        unsafe {
+            gl_window.make_current().unwrap();
+        }
+
+        Window {
+            events_loop,
+            gl_window,
+            width,
+            height,
+            is_close_requested: false
+        }
+    }
+}
+
+pub fn resize_window(window: &mut Window, width: u32, height: u32) {
+    window.gl_window.resize(width, height);
+    window.width = width;
+    window.height = height;
+}
+
+pub fn display(window: &Window) {
+    window.gl_window.swap_buffers().unwrap();
+}
+
+// Draws the cells as textured quads into the window's OpenGL context
+#[allow(dead_code)]
+pub struct GlBackend {
+    vao_id: GLuint,
+    vbo_id: GLuint,
+    ebo_id: GLuint,
+    vertex_data: Vec<f32>,
+    element_data: Vec<u32>
+}
+
+impl GlBackend {
+    pub fn new(window: &Window) -> GlBackend {
+        gl::load_with(|symbol| window.gl_window.get_proc_address(symbol) as *const _);
+
+        let mut vao: GLuint = 0;
+        let mut vbo: GLuint = 0;
+        let mut ebo: GLuint = 0;
+
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+
+            gl::GenVertexArrays(1, &mut vao);
+            gl::BindVertexArray(vao);
+
+            gl::GenBuffers(1, &mut ebo);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+            let vertex_shader: GLuint = compile_shader(gl::VERTEX_SHADER, VS_SRC);
+            let fragment_shader: GLuint = compile_shader(gl::FRAGMENT_SHADER, FS_SRC);
+
+            let shader_program: GLuint = gl::CreateProgram();
+            gl::AttachShader(shader_program, vertex_shader);
+            gl::AttachShader(shader_program, fragment_shader);
+            gl::LinkProgram(shader_program);
+            gl::UseProgram(shader_program);
+
+            let position_attribute: GLint = gl::GetAttribLocation(shader_program, b"position\0".as_ptr() as *const _);
+            gl::VertexAttribPointer(position_attribute as GLuint, 2, gl::FLOAT, 0,
+                                    7 * mem::size_of::<f32>() as GLsizei,
+                                    ptr::null());
+
+            let color_attribute: GLint = gl::GetAttribLocation(shader_program, b"color\0".as_ptr() as *const _);
+            gl::VertexAttribPointer(color_attribute as GLuint, 3, gl::FLOAT, 0,
+                                    7 * mem::size_of::<f32>() as GLsizei,
+                                    (2 * mem::size_of::<f32>()) as *const _);
+
+            let uv_attribute: GLint = gl::GetAttribLocation(shader_program, b"uv\0".as_ptr() as *const _);
+            gl::VertexAttribPointer(uv_attribute as GLuint, 2, gl::FLOAT, 0,
+                                    7 * mem::size_of::<f32>() as GLsizei,
+                                    (5 * mem::size_of::<f32>()) as *const _);
+
+            gl::EnableVertexAttribArray(position_attribute as GLuint);
+            gl::EnableVertexAttribArray(color_attribute as GLuint);
+            gl::EnableVertexAttribArray(uv_attribute as GLuint);
+
+            let image: RgbaImage = load_font();
+
+            let width: GLint = image.width() as GLint;
+            let height: GLint = image.height() as GLint;
+            let pixels: Vec<u8> = image.into_raw();
+
+            let mut texture_id: GLuint = 0;
+            gl::GenTextures(1, &mut texture_id);
+            gl::BindTexture(gl::TEXTURE_2D, texture_id);
+            gl::TexImage2D(gl::TEXTURE_2D,
+                           0,
+                           gl::RGBA8 as GLint,
+                           width,
+                           height,
+                           0,
+                           gl::RGBA,
+                           gl::UNSIGNED_BYTE,
+                           pixels.as_ptr() as *const _);
+
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+
+            let translation: Vector3<f32> = Vector3::new(0.0, 0.0, 0.0);
+
+            let model: Matrix4<f32> = Isometry3::new(translation, nalgebra::zero()).to_homogeneous();
+            let view: Matrix4<f32> = Isometry3::new(Vector3::new(0.0, 0.0, -1.0), nalgebra::zero()).to_homogeneous();
+            let projection: Matrix4<f32> = Orthographic3::new(0.0, window.width as f32, 0.0, window.height as f32, 0.1, 1000.0).unwrap();
+            let model_view_projection = projection * model * view;
+
+            let uni_model_view_projection = gl::GetUniformLocation(shader_program, b"modelViewProjection\0".as_ptr() as *const _);
+            gl::UniformMatrix4fv(uni_model_view_projection, 1, gl::FALSE, model_view_projection.as_slice().as_ptr() as *const _);
+        }
+
+        let version = unsafe {
+            let data = CStr::from_ptr(gl::GetString(gl::VERSION) as *const _).to_bytes().to_vec();
+            String::from_utf8(data).unwrap()
+        };
+
+        info!("OpenGL version {}", version);
+
+        GlBackend {
+            vao_id: vao,
+            vbo_id: vbo,
+            ebo_id: ebo,
+            vertex_data: Vec::new(),
+            element_data: Vec::new()
+        }
+    }
+}
+
+impl Backend for GlBackend {
+    fn upload(&mut self, cells: &[Sprite], cols: u32, rows: u32) {
+        self.vertex_data.clear();
+        self.element_data.clear();
+
+        // Construct render mesh
+        for row in 0..rows {
+            for col in 0..cols {
+                let index: usize = ((row * cols) + col) as usize;
+                let cell: Sprite = cells[index];
+
+                if cell.graphic == ' ' {
+                    continue;
+                }
+
+                let vertex_count: u32 = self.vertex_data.len() as u32 / 7;
+                let x_offset: f32 = (col * CELL_WIDTH) as f32;
+                let y_offset: f32 = (row * CELL_HEIGHT) as f32;
+
+                let (sprite_x, sprite_y) = glyph_position(cell.graphic);
+                let sprite_width: f32 = CELL_WIDTH as f32 / 128.0;
+                let sprite_height: f32 = CELL_HEIGHT as f32 / 256.0;
+                let u: f32 = sprite_x as f32 / 128.0;
+                let v: f32 = sprite_y as f32 / 256.0;
+
+                let r: f32 = cell.color.r;
+                let g: f32 = cell.color.g;
+                let b: f32 = cell.color.b;
+                //let a: f32 = cell.color.a;
+
+                let new_vertices: [f32; 28] = [
+                    // Top left
+                    x_offset, y_offset + CELL_HEIGHT as f32, r, g, b, u, v,
+                    // Top right
+                    x_offset + CELL_WIDTH as f32, y_offset + CELL_HEIGHT as f32, r, g, b, u + sprite_width, v,
+                    // Bottom right
+                    x_offset + CELL_WIDTH as f32, y_offset, r, g, b, u + sprite_width, v + sprite_height,
+                    // Bottom left
+                    x_offset, y_offset, r, g, b, u, v + sprite_height
+                ];
+
+                let new_elements: [u32; 6] = [
+                    vertex_count, vertex_count + 1, vertex_count + 2,
+                    vertex_count + 2, vertex_count + 3, vertex_count
+                ];
+
+                self.vertex_data.extend_from_slice(&new_vertices);
+                self.element_data.extend_from_slice(&new_elements);
+            }
+        }
+
+        // Upload vertices
+
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo_id);
+            gl::BufferData(gl::ARRAY_BUFFER,
+                           (self.vertex_data.len() * mem::size_of::<f32>()) as gl::types::GLsizeiptr,
+                           self.vertex_data.as_ptr() as *const _, gl::STATIC_DRAW);
+
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo_id);
+            gl::BufferData(gl::ELEMENT_ARRAY_BUFFER,
+                           (self.element_data.len() * mem::size_of::<u32>()) as gl::types::GLsizeiptr,
+                           self.element_data.as_ptr() as *const _, gl::STATIC_DRAW);
+        }
+    }
+
+    fn render(&mut self) {
+        unsafe {
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl::DrawElements(gl::TRIANGLES, self.element_data.len() as i32, gl::UNSIGNED_INT, ptr::null());
+        }
+    }
+}
+
+fn compile_shader(shader_type: GLenum, source: &[u8]) -> GLuint {
+    unsafe {
+        let shader_id: GLuint = gl::CreateShader(shader_type);
+        gl::ShaderSource(shader_id, 1, [source.as_ptr() as *const _].as_ptr(), ptr::null());
+        gl::CompileShader(shader_id);
+
+        let mut status: GLint = gl::TRUE as GLint;
+        gl::GetShaderiv(shader_id, gl::COMPILE_STATUS, &mut status as *mut GLint);
+        if status == (gl::FALSE as GLint) {
+            panic!("Shader compilation failed!");
+        }
+
+        return shader_id;
+    }
+}
@@ -51,7 +51,7 @@ pub fn process_events(window: &mut Window, input_man: &mut InputMan) {
         match event {
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::Closed => { window.is_close_requested = true; },
-                WindowEvent::Resized(w, h) => { println!("Resize to {}, {}", w, h); resize_window(window, w, h); },
+                WindowEvent::Resized(w, h) => { debug!("Resize to {}, {}", w, h); resize_window(window, w, h); },
                 WindowEvent::KeyboardInput { input, .. } => { input::process_key_input(input_man, &input); },
                 _ => ()
             },
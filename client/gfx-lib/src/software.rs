@@ -0,0 +1,66 @@
+use image::{Rgba, RgbaImage};
+use {Backend, Sprite, CELL_WIDTH, CELL_HEIGHT, load_font, glyph_position};
+
+// Rasterizes the cells into an RGBA image in memory, the same way the OpenGL backend draws them:
+// each glyph is tinted by its cell's color and blended over black. Needs no display, so it works on CI.
+pub struct SoftwareBackend {
+    font: RgbaImage,
+    image: RgbaImage
+}
+
+impl SoftwareBackend {
+    pub fn new(cols: u32, rows: u32) -> SoftwareBackend {
+        SoftwareBackend::with_font(cols, rows, load_font())
+    }
+
+    // `font` is laid out like the built in one
+    pub fn with_font(cols: u32, rows: u32, font: RgbaImage) -> SoftwareBackend {
+        SoftwareBackend {
+            font,
+            image: RgbaImage::from_pixel(cols * CELL_WIDTH, rows * CELL_HEIGHT, Rgba([0, 0, 0, 255]))
+        }
+    }
+
+    // Everything drawn so far, with the top row of cells at the top of the image
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+}
+
+impl Backend for SoftwareBackend {
+    fn upload(&mut self, cells: &[Sprite], cols: u32, rows: u32) {
+        for pixel in self.image.pixels_mut() {
+            *pixel = Rgba([0, 0, 0, 255]);
+        }
+
+        for row in 0..rows {
+            for col in 0..cols {
+                let cell: Sprite = cells[((row * cols) + col) as usize];
+                if cell.graphic == ' ' {
+                    continue;
+                }
+
+                // Row 0 is at the bottom of the screen, but at the top of the image
+                let x_offset: u32 = col * CELL_WIDTH;
+                let y_offset: u32 = (rows - 1 - row) * CELL_HEIGHT;
+                let (glyph_x, glyph_y) = glyph_position(cell.graphic);
+
+                for y in 0..CELL_HEIGHT {
+                    for x in 0..CELL_WIDTH {
+                        let texel: Rgba<u8> = *self.font.get_pixel(glyph_x + x, glyph_y + y);
+                        let alpha: f32 = texel[3] as f32 / 255.0;
+                        let tint: [f32; 3] = [cell.color.r, cell.color.g, cell.color.b];
+
+                        let pixel: &mut Rgba<u8> = self.image.get_pixel_mut(x_offset + x, y_offset + y);
+                        for channel in 0..3 {
+                            let source: f32 = texel[channel] as f32 * tint[channel];
+                            pixel[channel] = (source * alpha + pixel[channel] as f32 * (1.0 - alpha)).round() as u8;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn render(&mut self) {}
+}
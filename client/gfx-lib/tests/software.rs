@@ -0,0 +1,143 @@
+extern crate gfx;
+extern crate image;
+
+use std::env;
+use std::path::PathBuf;
+use image::{Rgba, RgbaImage};
+use gfx::*;
+
+fn black() -> Rgba<u8> {
+    Rgba([0, 0, 0, 255])
+}
+
+// Compares against tests/snapshots/<name>.png. Run with UPDATE_SNAPSHOTS=1 to write it instead,
+// then look at the new image before committing it.
+fn assert_snapshot(name: &str, image: &RgbaImage) {
+    let path: PathBuf = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("snapshots").join(format!("{}.png", name));
+
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        image.save(&path).unwrap();
+        return;
+    }
+
+    let expected: RgbaImage = match image::open(&path) {
+        Ok(expected) => expected.to_rgba(),
+        Err(e) => panic!("No snapshot at {} ({}). Run with UPDATE_SNAPSHOTS=1 to create it.", path.display(), e)
+    };
+
+    assert_eq!(expected.dimensions(), image.dimensions(), "{} changed size", name);
+    assert!(expected.clone().into_raw() == image.clone().into_raw(), "{} no longer matches {}", name, path.display());
+}
+
+// Pixels of one cell, with row 0 at the bottom like the renderer
+fn cell_pixels(image: &RgbaImage, rows: u32, x: u32, y: u32) -> Vec<Rgba<u8>> {
+    let mut pixels: Vec<Rgba<u8>> = Vec::new();
+    for py in 0..CELL_HEIGHT {
+        for px in 0..CELL_WIDTH {
+            pixels.push(*image.get_pixel(x * CELL_WIDTH + px, (rows - 1 - y) * CELL_HEIGHT + py));
+        }
+    }
+
+    pixels
+}
+
+#[test]
+fn blank_screens_are_black() {
+    let mut renderer = Renderer::headless(4, 2);
+    clear(&mut renderer);
+    render(&mut renderer);
+
+    let image: &RgbaImage = renderer.backend.image();
+    assert_eq!(image.dimensions(), (4 * CELL_WIDTH, 2 * CELL_HEIGHT));
+    assert!(image.pixels().all(|pixel| *pixel == black()));
+}
+
+#[test]
+fn glyphs_are_copied_from_the_font() {
+    let mut renderer = Renderer::headless(2, 2);
+    draw_string(&mut renderer, 0, 0, "A");
+    render(&mut renderer);
+
+    // White text over black is just the glyph, weighted by its alpha
+    let font: RgbaImage = load_font();
+    let (glyph_x, glyph_y) = glyph_position('A');
+    let mut expected: Vec<Rgba<u8>> = Vec::new();
+    for y in 0..CELL_HEIGHT {
+        for x in 0..CELL_WIDTH {
+            let texel: Rgba<u8> = *font.get_pixel(glyph_x + x, glyph_y + y);
+            let alpha: f32 = texel[3] as f32 / 255.0;
+            let channel = |i: usize| (texel[i] as f32 * alpha).round() as u8;
+            expected.push(Rgba([channel(0), channel(1), channel(2), 255]));
+        }
+    }
+
+    let image: &RgbaImage = renderer.backend.image();
+    let drawn: Vec<Rgba<u8>> = cell_pixels(image, 2, 0, 0);
+    assert_eq!(drawn, expected);
+    assert!(drawn.iter().any(|pixel| *pixel != black()));
+
+    for &(x, y) in &[(1, 0), (0, 1), (1, 1)] {
+        assert!(cell_pixels(image, 2, x, y).iter().all(|pixel| *pixel == black()));
+    }
+}
+
+#[test]
+fn cells_are_tinted_by_their_color() {
+    let mut renderer = Renderer::headless(1, 1);
+    draw_cell(&mut renderer, 0, 0, Sprite::new('#', COLOR_RED));
+    render(&mut renderer);
+
+    let image: &RgbaImage = renderer.backend.image();
+    assert!(image.pixels().any(|pixel| pixel[0] > 0));
+    assert!(image.pixels().all(|pixel| pixel[1] == 0 && pixel[2] == 0));
+}
+
+#[test]
+fn cells_outside_the_grid_are_ignored() {
+    let mut renderer = Renderer::headless(2, 1);
+    draw_cell(&mut renderer, -1, 0, Sprite::new('X', COLOR_WHITE));
+    draw_cell(&mut renderer, 2, 0, Sprite::new('X', COLOR_WHITE));
+    draw_cell(&mut renderer, 0, 1, Sprite::new('X', COLOR_WHITE));
+    render(&mut renderer);
+
+    assert_eq!(renderer.cell(-1, 0), None);
+    assert_eq!(renderer.cell(0, 0), Some(SPRITE_NONE));
+    assert!(renderer.backend.image().pixels().all(|pixel| *pixel == black()));
+}
+
+#[test]
+fn clearing_removes_the_previous_frame() {
+    let mut renderer = Renderer::headless(3, 1);
+    draw_string(&mut renderer, 0, 0, "abc");
+    render(&mut renderer);
+    assert!(renderer.backend.image().pixels().any(|pixel| *pixel != black()));
+
+    clear(&mut renderer);
+    render(&mut renderer);
+    assert!(renderer.backend.image().pixels().all(|pixel| *pixel == black()));
+}
+
+#[test]
+fn chat_screen_matches_snapshot() {
+    // Laid out like the client: messages going up from the bottom, the input line under them
+    let mut renderer = Renderer::headless(50, 15);
+    clear(&mut renderer);
+
+    let messages: [&str; 4] = [
+        "Connected to Rustychat as alice.",
+        "Welcome to Rustychat!",
+        "alice joined #general",
+        "<bob> hi alice"
+    ];
+
+    for (line, message) in messages.iter().rev().enumerate() {
+        draw_string(&mut renderer, 0, 1 + line as i32, message);
+    }
+
+    draw_string(&mut renderer, 0, 0, "#general> hello");
+    draw_box(&mut renderer, 30, 8, 20, 7);
+    draw_cell(&mut renderer, 49, 0, Sprite::new('*', COLOR_GREEN));
+    render(&mut renderer);
+
+    assert_snapshot("chat_screen", renderer.backend.image());
+}
@@ -0,0 +1,249 @@
+extern crate crossterm;
+extern crate rustychat_client;
+
+use std::env;
+use std::io::{self, Stdout, Write};
+use std::process;
+use std::time::Duration;
+use crossterm::{cursor, event, execute, queue, terminal};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use rustychat_client::{arg_value, logger, ChatClient, TcpConnector};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    // Log lines would be written over the screen, so nothing is logged unless asked for.
+    // Redirect stderr to keep them, e.g. --log-level info 2>client.log
+    let log_level: String = arg_value(&args, "--log-level").unwrap_or_else(|| String::from("off"));
+    if let Err(e) = logger::init(&log_level, None) {
+        eprintln!("Failed to start logging: {}", e);
+    }
+
+    let connector: TcpConnector = match TcpConnector::from_args(&args) {
+        Ok(connector) => connector,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+    let mut client: ChatClient<TcpConnector> = ChatClient::new(connector, arg_value(&args, "--nick"), arg_value(&args, "--password"));
+
+    let mut screen: Screen = match Screen::enter() {
+        Ok(screen) => screen,
+        Err(e) => {
+            eprintln!("Failed to set up the terminal: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut input: String = String::new();
+
+    // How many lines up from the newest the scrollback pane is showing
+    let mut scroll: usize = 0;
+
+    if let Err(e) = run(&mut screen, &mut client, &mut input, &mut scroll) {
+        drop(screen);
+        eprintln!("Terminal error: {}", e);
+        process::exit(1);
+    }
+}
+
+// Until Esc or Ctrl-C
+fn run(screen: &mut Screen, client: &mut ChatClient<TcpConnector>, input: &mut String, scroll: &mut usize) -> io::Result<()> {
+    loop {
+        // UI
+        screen.draw(client, input, scroll)?;
+
+        if event::poll(Duration::from_millis(20))? {
+            let is_quitting: bool = match event::read()? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => !handle_key(key, screen, client, input, scroll),
+                Event::Resize(_, _) => {
+                    screen.invalidate();
+                    false
+                },
+                _ => false
+            };
+
+            if is_quitting {
+                return Ok(());
+            }
+        }
+
+        // Networking
+        client.update(Duration::from_millis(1));
+    }
+}
+
+// The same keys as the window client, plus scrolling. Returns false to quit.
+fn handle_key(key: KeyEvent, screen: &Screen, client: &mut ChatClient<TcpConnector>, input: &mut String, scroll: &mut usize) -> bool {
+    let is_control: bool = key.modifiers.contains(KeyModifiers::CONTROL);
+
+    match key.code {
+        KeyCode::Esc => return false,
+        KeyCode::Char('c') | KeyCode::Char('d') if is_control => return false,
+        KeyCode::Enter => {
+            client.submit(input);
+            input.clear();
+            *scroll = 0;
+        },
+        KeyCode::Backspace => {
+            input.pop();
+        },
+        KeyCode::Tab => client.next_channel(),
+        // Scrolls back, and once at the top, fetches older history from the server
+        KeyCode::PageUp => {
+            if *scroll >= screen.max_scroll {
+                client.request_history();
+                *scroll = 0;
+            }
+            else {
+                *scroll = (*scroll + screen.page).min(screen.max_scroll);
+            }
+        },
+        KeyCode::PageDown => *scroll = scroll.saturating_sub(screen.page),
+        KeyCode::Char(c) if !is_control => input.push(c),
+        _ => ()
+    }
+
+    true
+}
+
+// The terminal, switched to raw mode and the alternate screen until dropped.
+// Holds what is on it so only the rows that change are written.
+struct Screen {
+    stdout: Stdout,
+    rows: Vec<String>,
+    // Lines in a page of scrollback, and how far up it goes, as of the last draw
+    page: usize,
+    max_scroll: usize
+}
+
+impl Screen {
+    fn enter() -> io::Result<Screen> {
+        let mut stdout: Stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen, terminal::Clear(ClearType::All))?;
+
+        Ok(Screen {
+            stdout,
+            rows: Vec::new(),
+            page: 0,
+            max_scroll: 0
+        })
+    }
+
+    // Redraws everything next time, e.g. after the terminal is resized
+    fn invalidate(&mut self) {
+        self.rows.clear();
+    }
+
+    // The scrollback pane, with the status bar and input line under it
+    fn draw(&mut self, client: &ChatClient<TcpConnector>, input: &str, scroll: &mut usize) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let width: usize = width as usize;
+        let height: usize = height as usize;
+
+        let lines: Vec<String> = wrap(&client.messages, width);
+        self.page = height.saturating_sub(2);
+        self.max_scroll = lines.len().saturating_sub(self.page);
+        *scroll = (*scroll).min(self.max_scroll);
+
+        let end: usize = lines.len() - *scroll;
+        let start: usize = end.saturating_sub(self.page);
+
+        let mut rows: Vec<String> = vec![String::new(); self.page - (end - start)];
+        rows.extend_from_slice(&lines[start..end]);
+        rows.push(status_bar(client, *scroll));
+
+        // Keeps the end of a long input line, where the cursor is, in view
+        let prompt: String = format!("{}> {}", client.channel(), client.mask_input(input));
+        let skip: usize = (prompt.chars().count() + 1).saturating_sub(width);
+        rows.push(prompt.chars().skip(skip).collect());
+
+        if rows.len() != self.rows.len() {
+            queue!(self.stdout, terminal::Clear(ClearType::All))?;
+            self.rows.clear();
+        }
+
+        let status_row: usize = rows.len().saturating_sub(2);
+        for (i, row) in rows.iter().enumerate().take(height) {
+            if self.rows.get(i) == Some(row) {
+                continue;
+            }
+
+            let row: String = row.chars().take(width).collect();
+            queue!(self.stdout, cursor::MoveTo(0, i as u16))?;
+
+            if i == status_row {
+                let padding: String = " ".repeat(width - row.chars().count());
+                queue!(self.stdout, SetAttribute(Attribute::Reverse), Print(row), Print(padding), SetAttribute(Attribute::Reset))?;
+            }
+            else {
+                queue!(self.stdout, Print(row), terminal::Clear(ClearType::UntilNewLine))?;
+            }
+        }
+
+        let cursor_column: usize = rows.last().map_or(0, |prompt| prompt.chars().count());
+        queue!(self.stdout, cursor::MoveTo(cursor_column as u16, height.saturating_sub(1) as u16))?;
+        self.stdout.flush()?;
+
+        self.rows = rows;
+
+        Ok(())
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(self.stdout, LeaveAlternateScreen, cursor::Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// " 127.0.0.1:7667 | alice | #general [#rust] | offline, retrying in 3s"
+fn status_bar(client: &ChatClient<TcpConnector>, scroll: usize) -> String {
+    let mut parts: Vec<String> = vec![client.connector().host.clone()];
+    parts.push(String::from(client.username().unwrap_or("not logged in")));
+
+    // The channel typed lines go to is in brackets
+    let channels: Vec<String> = client.channels().iter()
+        .map(|name| if name == client.channel() { format!("[{}]", name) } else { name.clone() })
+        .collect();
+
+    if !channels.is_empty() {
+        parts.push(channels.join(" "));
+    }
+
+    let status: String = client.status();
+    let status: &str = status.trim().trim_start_matches('[').trim_end_matches(']');
+    if !status.is_empty() {
+        parts.push(String::from(status));
+    }
+
+    if scroll > 0 {
+        parts.push(format!("{} more below, PageDown", scroll));
+    }
+
+    format!(" {}", parts.join(" | "))
+}
+
+// Splits messages into lines of at most `width` characters
+fn wrap(messages: &[String], width: usize) -> Vec<String> {
+    let width: usize = width.max(1);
+    let mut lines: Vec<String> = Vec::new();
+
+    for message in messages {
+        let chars: Vec<char> = message.chars().collect();
+        if chars.is_empty() {
+            lines.push(String::new());
+        }
+
+        for chunk in chars.chunks(width) {
+            lines.push(chunk.iter().collect());
+        }
+    }
+
+    lines
+}
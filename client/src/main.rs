@@ -1,61 +1,39 @@
-extern crate mio;
-extern crate doosknet;
 extern crate gfx;
-extern crate rand;
+extern crate rustychat_client;
 
 use std::env;
+use std::process;
 use std::time::Duration;
-use std::collections::VecDeque;
-use std::io::{self, Read};
-use std::str;
-use std::net::ToSocketAddrs;
-use mio::*;
-use mio::net::TcpStream;
-use gfx::{input, Window, Renderer};
+use gfx::{input, Window, Renderer, GlBackend, Backend};
 use gfx::input::{InputMan};
-use rand::Rng;
-use doosknet::*;
+use rustychat_client::{arg_value, logger, ChatClient, TcpConnector};
 
 fn main() {
-    let mut addr = "127.0.0.1:7667".to_socket_addrs().unwrap();
-
     let args: Vec<String> = env::args().collect();
-    if args.len() >= 2 {
-        addr = args[1].to_socket_addrs().unwrap();
+
+    // e.g. --log-level debug, or info,doosknet=trace to see every read and write
+    let log_level: String = arg_value(&args, "--log-level").unwrap_or_else(|| String::from("info"));
+    if let Err(e) = logger::init(&log_level, None) {
+        eprintln!("Failed to start logging: {}", e);
     }
 
+    let connector: TcpConnector = match TcpConnector::from_args(&args) {
+        Ok(connector) => connector,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+    let mut client: ChatClient<TcpConnector> = ChatClient::new(connector, arg_value(&args, "--nick"), arg_value(&args, "--password"));
+
     let window_title: &str = "Rustychat";
     let window_width: u32 = 50 * gfx::CELL_WIDTH;
     let window_height: u32 = 15 * gfx::CELL_HEIGHT;
 
     let mut window: Window = Window::new(window_title, window_width, window_height);
-    let mut renderer: Renderer = Renderer::new(&window);
+    let mut renderer: Renderer<GlBackend> = Renderer::new(&window);
     let mut input_man: InputMan = InputMan::new();
 
-    // Setup the client socket
-    let mut socket = TcpStream::connect(&addr.next().unwrap()).unwrap();
-
-    // Create a poll instance
-    let poll = Poll::new().unwrap();
-
-    // Register the socket
-    poll.register(&socket, LOCAL_TOKEN, Ready::readable() | Ready::writable(), PollOpt::edge()).unwrap();
-
-    // Create storage for events
-    let mut events = Events::with_capacity(1024);
-
-    let mut buffer: NetworkBuffer = NetworkBuffer::new();
-
-    let mut is_disconnected: bool = false;
-
-    let mut incoming_packets: VecDeque<Packet> = VecDeque::new();
-    let mut outgoing_packets: VecDeque<Packet> = VecDeque::new();
-
-    let mut messages: Vec<String> = Vec::new();
-
-    let usernames = vec!["Bob", "Bill", "Jeb", "Jib", "Jim", "Mitch", "Deboe", "John"];
-    let username: &str = rand::thread_rng().choose(&usernames).unwrap();
-
     loop {
         // UI
         input::process_events(&mut window, &mut input_man);
@@ -66,104 +44,35 @@ fn main() {
         if input::is_key_pressed(&input_man, input::VirtualKeyCode::Return) {
             let message: String = input_man.input_string.clone();
             input_man.clear_input_string();
-
-            if message.len() != 0 {
-                let packet = Packet::new(username, &message);
-                outgoing_packets.push_back(packet);
-            }
+            client.submit(&message);
         }
 
-        gfx::clear(&mut renderer);
-
-        let mut line_count = 0;
-        for message in messages.iter().rev() {
-            if line_count >= 32 {
-                break;
-            }
-
-            gfx::draw_string(&mut renderer, 0, 1 + line_count, &message);
-            line_count += 1;
+        if input::is_key_pressed(&input_man, input::VirtualKeyCode::PageUp) {
+            client.request_history();
         }
 
-        gfx::draw_string(&mut renderer, 0, 0, &format!("> {}", input_man.input_string));
+        if input::is_key_pressed(&input_man, input::VirtualKeyCode::Tab) {
+            client.next_channel();
+        }
 
-        gfx::render(&mut renderer);
+        draw_screen(&mut renderer, &client.messages, &client.prompt(&input_man.input_string));
         gfx::display(&window);
 
         input::update_input(&mut input_man);
 
         // Networking
-        poll.poll(&mut events, Some(Duration::from_millis(1))).unwrap();
-        for event in events.iter() {
-            match event.token() {
-                LOCAL_TOKEN => {
-                    if event.readiness().is_readable() {
-                        loop {
-                            // Read until there are no more incoming bytes
-                            match socket.read(&mut buffer.data[buffer.offset..]) {
-                                Ok(0) => {
-                                    // Socket is closed
-                                    println!("Disconnected from server!");
-                                    is_disconnected = true;
-                                    break;
-                                },
-                                Ok(read_bytes) => {
-                                    buffer.offset += read_bytes;
-                                    println!("Read {} bytes from server", read_bytes);
-                                },
-                                Err(e) => {
-                                    if e.kind() == io::ErrorKind::WouldBlock {
-                                        // Socket is not ready anymore, stop reading
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    else if event.readiness().is_writable() {
-                        if outgoing_packets.is_empty() {
-                            continue;
-                        }
-
-                        while let Some(packet) = outgoing_packets.pop_front() {
-                            let data = serialize_packet(packet);
-                            match send_bytes(&mut socket, &data) {
-                                Ok(sent_bytes) => {
-                                    println!("Sent {} bytes", sent_bytes);
-                                },
-                                Err(e) => {
-                                    eprintln!("send_bytes() failed with error {:?}", e);
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                },
-                _ => unreachable!()
-            }
-        }
-
-        if is_disconnected {
-            println!("Connection closed.");
-            break;
-        }
-
-        // Need to reregister for events
-        poll.reregister(&socket, LOCAL_TOKEN, Ready::readable() | Ready::writable(), PollOpt::edge()).unwrap();
+        client.update(Duration::from_millis(1));
+    }
+}
 
-        // Process incoming bytes to create packets
-        if buffer.offset == 0 {
-            continue;
-        }
+// The newest messages going up from the bottom of the screen, with the prompt under them
+fn draw_screen<B: Backend>(renderer: &mut Renderer<B>, messages: &[String], prompt: &str) {
+    gfx::clear(renderer);
 
-        while let Some(packet) = deserialize_packet(&mut buffer) {
-            incoming_packets.push_back(packet);
-        }
-
-        // Handle packets
-        while let Some(packet) = incoming_packets.pop_front() {
-            println!("> {}", packet.message);
-            messages.push(format!("{} says \"{}\"", packet.sender, packet.message));
-        }
+    for (line_count, message) in messages.iter().rev().take(renderer.rows as usize).enumerate() {
+        gfx::draw_string(renderer, 0, 1 + line_count as i32, message);
     }
-}
\ No newline at end of file
+
+    gfx::draw_string(renderer, 0, 0, prompt);
+    gfx::render(renderer);
+}
@@ -0,0 +1,162 @@
+use std::io::Read;
+use error::Error;
+use MAX_PACKET_SIZE;
+
+pub const DEFAULT_BUFFER_CAPACITY: usize = 1024;
+pub const MAX_BUFFER_SIZE: usize = 64 * 1024;
+
+// Don't bother reading into less free space than this if compacting or growing would give us more
+const MIN_READ_SIZE: usize = 256;
+
+// A contiguous receive buffer. Bytes are appended at the tail and consumed from the head,
+// so complete frames can always be decoded straight out of `as_slice()` without copying.
+// It never wraps around: consumed space at the head is reclaimed by compacting, shifting
+// the unread bytes back to the start of the storage, before the buffer is allowed to grow.
+pub struct NetworkBuffer {
+    data: Vec<u8>,
+    start: usize,
+    end: usize,
+    max_capacity: usize
+}
+
+impl NetworkBuffer {
+    pub fn new() -> Self {
+        NetworkBuffer::with_limits(DEFAULT_BUFFER_CAPACITY, MAX_BUFFER_SIZE)
+    }
+
+    // The maximum capacity is never allowed to be smaller than a single packet
+    pub fn with_limits(initial_capacity: usize, max_capacity: usize) -> Self {
+        let max_capacity: usize = max_capacity.max(MAX_PACKET_SIZE);
+        let initial_capacity: usize = initial_capacity.max(1).min(max_capacity);
+
+        NetworkBuffer {
+            data: vec![0; initial_capacity],
+            start: 0,
+            end: 0,
+            max_capacity
+        }
+    }
+
+    // Number of unread bytes
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    // Current size of the underlying storage
+    pub fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn max_capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    // True when no more bytes can be accepted until some are drained
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.max_capacity
+    }
+
+    // The unread bytes, in order
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[self.start..self.end]
+    }
+
+    // Free space at the tail, compacting or growing the storage first if that is worthwhile.
+    // The returned slice is only empty when the buffer is full.
+    pub fn write_slice(&mut self) -> &mut [u8] {
+        if self.is_empty() {
+            self.start = 0;
+            self.end = 0;
+        }
+
+        if (self.data.len() - self.end) < MIN_READ_SIZE && self.start > 0 {
+            self.compact();
+        }
+
+        if (self.data.len() - self.end) < MIN_READ_SIZE && self.data.len() < self.max_capacity {
+            let new_capacity: usize = (self.data.len() * 2).max(MIN_READ_SIZE).min(self.max_capacity);
+            self.data.resize(new_capacity, 0);
+        }
+
+        &mut self.data[self.end..]
+    }
+
+    // Marks `count` bytes of the slice returned by `write_slice` as filled
+    pub fn commit(&mut self, count: usize) {
+        assert!(self.end + count <= self.data.len(), "Committed more bytes than were available");
+        self.end += count;
+    }
+
+    // Appends as many bytes as will fit, returning how many were taken
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) -> usize {
+        let mut written: usize = 0;
+
+        while written < bytes.len() {
+            let count: usize = {
+                let space = self.write_slice();
+                if space.is_empty() {
+                    break;
+                }
+
+                let count: usize = space.len().min(bytes.len() - written);
+                space[..count].copy_from_slice(&bytes[written..(written + count)]);
+                count
+            };
+
+            self.commit(count);
+            written += count;
+        }
+
+        written
+    }
+
+    // Performs a single read into the free space at the tail.
+    // Ok(0) always means the reader reached EOF, never that the buffer was full;
+    // a full buffer is reported as Error::BufferFull so callers can apply back-pressure.
+    pub fn read_from<R: Read>(&mut self, reader: &mut R) -> Result<usize, Error> {
+        let read_bytes: usize = {
+            let space = self.write_slice();
+            if space.is_empty() {
+                return Err(Error::BufferFull);
+            }
+
+            reader.read(space)?
+        };
+
+        self.commit(read_bytes);
+
+        Ok(read_bytes)
+    }
+
+    // Removes `count` bytes from the front of the buffer
+    pub fn drain(&mut self, count: usize) {
+        assert!(count <= self.len(), "Drained more bytes than were buffered");
+        self.start += count;
+
+        if self.is_empty() {
+            self.start = 0;
+            self.end = 0;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.end = 0;
+    }
+
+    fn compact(&mut self) {
+        self.data.copy_within(self.start..self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+    }
+}
+
+impl Default for NetworkBuffer {
+    fn default() -> Self {
+        NetworkBuffer::new()
+    }
+}
@@ -0,0 +1,690 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use mio::{Events, Poll, PollOpt, Ready, Registration, SetReadiness, Token};
+use mio::net::{TcpListener, TcpStream};
+use buffer::NetworkBuffer;
+use queue::SendQueue;
+use error::{Error, DecodeError};
+use stream::Stream;
+use pipe;
+use rustls::{ClientConfig, ServerConfig};
+use net2::TcpBuilder;
+use {deserialize_packet, serialize_packet, Message};
+
+pub const EVENT_CAPACITY: usize = 1024;
+
+// The client only ever has one connection, to the server
+pub const SERVER_CONN: ConnId = ConnId(0);
+
+// Far above any connection id. mio keeps usize::MAX for itself.
+const WAKER_TOKEN: Token = Token(usize::MAX - 1);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ConnId(pub usize);
+
+impl fmt::Display for ConnId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+#[derive(Debug)]
+pub enum NetworkEvent {
+    Connected(ConnId),
+    Disconnected(ConnId),
+    // The packet, and how many bytes its frame took up on the wire, header included
+    Message(ConnId, Message, usize),
+    // A packet could not be decoded. Unrecoverable errors also close the connection,
+    // though anything sent to it before the next poll is still delivered.
+    DecodeFailed(ConnId, DecodeError),
+    // The peer went quiet for longer than the heartbeat timeout. A Disconnected follows.
+    TimedOut(ConnId)
+}
+
+// Keeps an eye on quiet peers. Pings and pongs are answered and swallowed by the endpoint,
+// so they never show up as Message events.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Heartbeat {
+    // Ping the peer once nothing has been heard from it for this long
+    pub interval: Duration,
+    // Drop it once nothing has been heard from it for this long
+    pub timeout: Duration
+}
+
+// Makes a Server::poll that is waiting return straight away. Can be cloned and sent to other threads,
+// so they can hand work to the thread that polls.
+#[derive(Clone)]
+pub struct Waker {
+    readiness: SetReadiness
+}
+
+impl Waker {
+    pub fn wake(&self) -> Result<(), Error> {
+        self.readiness.set_readiness(Ready::readable())?;
+        Ok(())
+    }
+}
+
+// Every log record about a connection carries its id and peer address as the `conn` and `peer` fields
+struct Connection {
+    id: ConnId,
+    stream: Stream,
+    peer_addr: SocketAddr,
+    buffer: NetworkBuffer,
+    outgoing: SendQueue,
+    is_connected: bool,
+    is_closing: bool,
+    is_disconnected: bool,
+    // When we last heard anything from the peer, and last pinged it
+    last_received: Instant,
+    last_ping: Option<Instant>,
+    next_ping_id: u64
+}
+
+impl Connection {
+    fn new(id: ConnId, stream: Stream, peer_addr: SocketAddr, is_connected: bool) -> Self {
+        Connection {
+            id,
+            stream,
+            peer_addr,
+            buffer: NetworkBuffer::new(),
+            outgoing: SendQueue::new(),
+            is_connected,
+            is_closing: false,
+            is_disconnected: false,
+            last_received: Instant::now(),
+            last_ping: None,
+            next_ping_id: 0
+        }
+    }
+
+    // Only ask to hear about writability when there is something to write, or a close to finish
+    fn interest(&self) -> Ready {
+        if self.outgoing.is_empty() && !self.stream.wants_write() && self.is_connected && !self.is_closing {
+            Ready::readable()
+        }
+        else {
+            Ready::readable() | Ready::writable()
+        }
+    }
+
+    // Read until there are no more incoming bytes, or the buffer is full
+    fn receive(&mut self) {
+        loop {
+            match self.buffer.read_from(&mut self.stream) {
+                Ok(0) => {
+                    // Socket is closed
+                    debug!(conn:% = self.id, peer:% = self.peer_addr; "Peer closed the connection");
+                    self.is_disconnected = true;
+                    break;
+                },
+                Ok(size) => {
+                    trace!(conn:% = self.id, peer:% = self.peer_addr; "Read {} bytes", size);
+                    self.last_received = Instant::now();
+                },
+                Err(Error::BufferFull) => {
+                    // Leave the rest in the socket until we've decoded what we have
+                    break;
+                },
+                Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                    // Socket is not ready anymore, stop reading
+                    break;
+                },
+                Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => {
+                    debug!(conn:% = self.id, peer:% = self.peer_addr; "Read failed: {}", e);
+                    self.is_disconnected = true;
+                    break;
+                }
+            }
+        }
+    }
+
+    // Send as much of the outgoing queue as the socket will take
+    fn flush(&mut self) {
+        if !self.outgoing.is_empty() {
+            match self.outgoing.flush(&mut self.stream) {
+                Ok(size) => trace!(conn:% = self.id, peer:% = self.peer_addr; "Wrote {} bytes", size),
+                Err(e) => {
+                    debug!(conn:% = self.id, peer:% = self.peer_addr; "Write failed: {}", e);
+                    self.is_disconnected = true;
+                    return;
+                }
+            }
+        }
+
+        // Closing connections are dropped once they have sent everything
+        if self.is_closing && self.outgoing.is_empty() {
+            self.stream.send_close_notify();
+        }
+
+        match self.stream.write_pending() {
+            Ok(()) => {
+                if self.is_closing && self.outgoing.is_empty() {
+                    self.is_disconnected = true;
+                }
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+            Err(e) => {
+                debug!(conn:% = self.id, peer:% = self.peer_addr; "Write failed: {}", e);
+                self.is_disconnected = true;
+            }
+        }
+    }
+
+    fn decode(&mut self, id: ConnId, events: &mut Vec<NetworkEvent>) {
+        // Once a connection is closing, anything else it sends is ignored
+        while !self.is_closing && !self.buffer.is_empty() {
+            let buffered: usize = self.buffer.len();
+            match deserialize_packet(&mut self.buffer) {
+                Ok(Message::Ping(ping_id)) => {
+                    if self.outgoing.push(&Message::Pong(ping_id)).is_ok() {
+                        self.flush();
+                    }
+                },
+                // Hearing anything at all is enough, which receive() already noted
+                Ok(Message::Pong(_)) => (),
+                Ok(packet) => events.push(NetworkEvent::Message(id, packet, buffered - self.buffer.len())),
+                Err(DecodeError::Incomplete) => break,
+                Err(e) => {
+                    debug!(conn:% = self.id, peer:% = self.peer_addr; "Failed to decode a packet: {}", e);
+
+                    if !e.is_recoverable() {
+                        self.buffer.clear();
+                        self.is_closing = true;
+                    }
+
+                    events.push(NetworkEvent::DecodeFailed(id, e));
+                }
+            }
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.is_disconnected
+    }
+
+    // Pings a quiet peer, and gives up on one that has been quiet for too long. Returns true if it timed out.
+    fn check_heartbeat(&mut self, heartbeat: &Heartbeat, now: Instant) -> bool {
+        if !self.is_connected || self.is_finished() {
+            return false;
+        }
+
+        let quiet: Duration = now.saturating_duration_since(self.last_received);
+        if quiet >= heartbeat.timeout {
+            debug!(conn:% = self.id, peer:% = self.peer_addr; "Heard nothing for {:?}, giving up", quiet);
+            self.outgoing.clear();
+            self.is_disconnected = true;
+            return true;
+        }
+
+        let is_ping_due: bool = self.last_ping.is_none_or(|last_ping| last_ping < self.last_received || now.saturating_duration_since(last_ping) >= heartbeat.interval);
+        if quiet >= heartbeat.interval && is_ping_due {
+            self.next_ping_id += 1;
+            trace!(conn:% = self.id, peer:% = self.peer_addr; "Sending ping {}", self.next_ping_id);
+            if self.outgoing.push(&Message::Ping(self.next_ping_id)).is_ok() {
+                self.flush();
+            }
+
+            self.last_ping = Some(now);
+        }
+
+        false
+    }
+
+    // When check_heartbeat next has something to do
+    fn heartbeat_deadline(&self, heartbeat: &Heartbeat) -> Instant {
+        let ping_at: Instant = match self.last_ping {
+            Some(last_ping) if last_ping >= self.last_received => last_ping + heartbeat.interval,
+            _ => self.last_received + heartbeat.interval
+        };
+
+        ping_at.min(self.last_received + heartbeat.timeout)
+    }
+}
+
+// Shortens `timeout` so a poll wakes up in time for the earliest deadline
+fn poll_timeout(timeout: Option<Duration>, deadline: Option<Instant>) -> Option<Duration> {
+    let until_deadline: Duration = match deadline {
+        Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+        None => return timeout
+    };
+
+    Some(timeout.map_or(until_deadline, |timeout| timeout.min(until_deadline)))
+}
+
+fn bind_listener(addr: &SocketAddr) -> Result<TcpListener, Error> {
+    let builder: TcpBuilder = match *addr {
+        SocketAddr::V4(_) => TcpBuilder::new_v4()?,
+        SocketAddr::V6(_) => {
+            let builder = TcpBuilder::new_v6()?;
+            builder.only_v6(true)?;
+            builder
+        }
+    };
+
+    builder.reuse_address(true)?;
+    builder.bind(addr)?;
+
+    Ok(TcpListener::from_std(builder.listen(1024)?)?)
+}
+
+pub struct Server {
+    poll: Poll,
+    events: Events,
+    listeners: Vec<TcpListener>,
+    tls: Option<Arc<ServerConfig>>,
+    connections: HashMap<ConnId, Connection>,
+    heartbeat: Option<Heartbeat>,
+    // Created by the first call to waker()
+    waker: Option<(Registration, Waker)>,
+    // Pipe connections made since the last poll, which reports them as Connected
+    accepted: Vec<ConnId>,
+    next_id: usize
+}
+
+impl Server {
+    pub fn bind(addr: &SocketAddr) -> Result<Server, Error> {
+        Server::bind_all(&[*addr], None)
+    }
+
+    // Every accepted connection is wrapped in TLS using `config`
+    pub fn bind_tls(addr: &SocketAddr, config: Arc<ServerConfig>) -> Result<Server, Error> {
+        Server::bind_all(&[*addr], Some(config))
+    }
+
+    // Listens on every address in `addrs`. IPv6 listeners only accept IPv6 connections,
+    // so "0.0.0.0" and "::" can be bound on the same port side by side.
+    pub fn bind_all(addrs: &[SocketAddr], tls: Option<Arc<ServerConfig>>) -> Result<Server, Error> {
+        if addrs.is_empty() {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::InvalidInput, "No addresses to bind to")));
+        }
+
+        let poll = Poll::new()?;
+        let mut listeners: Vec<TcpListener> = Vec::new();
+
+        // Listener tokens come first, connection ids follow on after them
+        for (i, addr) in addrs.iter().enumerate() {
+            let listener = bind_listener(addr)?;
+            poll.register(&listener, Token(i), Ready::readable(), PollOpt::edge())?;
+            listeners.push(listener);
+        }
+
+        Ok(Server::with_listeners(poll, listeners, tls))
+    }
+
+    // A server that listens on nothing. Connections can only be made with `connect_pipe`,
+    // which keeps everything in this process; handy for tests.
+    pub fn unbound() -> Result<Server, Error> {
+        Ok(Server::with_listeners(Poll::new()?, Vec::new(), None))
+    }
+
+    fn with_listeners(poll: Poll, listeners: Vec<TcpListener>, tls: Option<Arc<ServerConfig>>) -> Server {
+        Server {
+            poll,
+            events: Events::with_capacity(EVENT_CAPACITY),
+            next_id: listeners.len(),
+            listeners,
+            tls,
+            connections: HashMap::new(),
+            heartbeat: None,
+            waker: None,
+            accepted: Vec::new()
+        }
+    }
+
+    // How many readiness events a single poll can return
+    pub fn set_event_capacity(&mut self, capacity: usize) {
+        self.events = Events::with_capacity(capacity.max(1));
+    }
+
+    // Pings quiet connections and drops dead ones. Off (None) by default.
+    pub fn set_heartbeat(&mut self, heartbeat: Option<Heartbeat>) {
+        self.heartbeat = heartbeat;
+    }
+
+    // A handle that interrupts poll from another thread. Every call returns a handle to the same waker.
+    pub fn waker(&mut self) -> Result<Waker, Error> {
+        if let Some((_, ref waker)) = self.waker {
+            return Ok(waker.clone());
+        }
+
+        let (registration, readiness) = Registration::new2();
+        self.poll.register(&registration, WAKER_TOKEN, Ready::readable(), PollOpt::edge())?;
+
+        let waker = Waker { readiness };
+        self.waker = Some((registration, waker.clone()));
+
+        Ok(waker)
+    }
+
+    // Connects a Client to this server over an in-process pipe. The server sees it as a connection from `peer_addr`,
+    // and reports it as Connected on the next poll. Works whether or not the server is listening.
+    pub fn connect_pipe(&mut self, peer_addr: &SocketAddr) -> Result<Client, Error> {
+        let (server_end, client_end) = pipe::pipe();
+        let stream: Stream = Stream::Pipe(server_end);
+
+        let id = ConnId(self.next_id);
+        self.next_id += 1;
+
+        self.poll.register(stream.evented(), Token(id.0), Ready::readable(), PollOpt::edge())?;
+        self.connections.insert(id, Connection::new(id, stream, *peer_addr, true));
+        self.accepted.push(id);
+        debug!(conn:% = id, peer:% = peer_addr; "Accepted a pipe connection");
+
+        let local_addr: SocketAddr = self.local_addr().unwrap_or_else(|_| SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0));
+        Client::connect_with(&local_addr, Stream::Pipe(client_end))
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        match self.listeners.first() {
+            Some(listener) => Ok(listener.local_addr()?),
+            None => Err(Error::Io(io::Error::new(io::ErrorKind::NotConnected, "Not listening")))
+        }
+    }
+
+    pub fn local_addrs(&self) -> Result<Vec<SocketAddr>, Error> {
+        let mut addrs: Vec<SocketAddr> = Vec::new();
+        for listener in &self.listeners {
+            addrs.push(listener.local_addr()?);
+        }
+
+        Ok(addrs)
+    }
+
+    // Closes the listening sockets, so no new connections come in. Existing connections carry on.
+    pub fn stop_listening(&mut self) -> Result<(), Error> {
+        for listener in self.listeners.drain(..) {
+            self.poll.deregister(&listener)?;
+        }
+
+        Ok(())
+    }
+
+    // Waits for network activity and returns everything that happened, in order
+    pub fn poll(&mut self, timeout: Option<Duration>) -> Result<Vec<NetworkEvent>, Error> {
+        // Need to reregister for events, since sends may have queued data since the last poll
+        for (id, connection) in &self.connections {
+            self.poll.reregister(connection.stream.evented(), Token(id.0), connection.interest(), PollOpt::edge())?;
+        }
+
+        let deadline: Option<Instant> = self.heartbeat.and_then(|heartbeat| {
+            self.connections.values().map(|connection| connection.heartbeat_deadline(&heartbeat)).min()
+        });
+
+        // Don't wait when there are pipe connections to report
+        let timeout: Option<Duration> = if self.accepted.is_empty() { timeout } else { Some(Duration::from_secs(0)) };
+        self.poll.poll(&mut self.events, poll_timeout(timeout, deadline))?;
+
+        let mut events: Vec<NetworkEvent> = self.accepted.drain(..)
+            .map(NetworkEvent::Connected)
+            .collect();
+
+        for event in self.events.iter() {
+            // Woken up. There is nothing to read; returning is the whole point.
+            if event.token() == WAKER_TOKEN {
+                if let Some((_, ref waker)) = self.waker {
+                    waker.readiness.set_readiness(Ready::empty())?;
+                }
+
+                continue;
+            }
+
+            if let Some(listener) = self.listeners.get(event.token().0) {
+                // Accept until there is nothing left, or a connection fails before we get to it
+                while let Ok((socket, peer_addr)) = listener.accept() {
+                    // Trouble with one connection is no reason to stop serving the rest, so it is just dropped
+                    let stream: Stream = match self.tls {
+                        Some(ref config) => match Stream::server(socket, config.clone()) {
+                            Ok(stream) => stream,
+                            Err(e) => {
+                                warn!(peer:% = peer_addr; "Dropping a connection, TLS setup failed: {}", e);
+                                continue;
+                            }
+                        },
+                        None => Stream::Plain(socket)
+                    };
+
+                    let id = ConnId(self.next_id);
+                    self.next_id += 1;
+
+                    if let Err(e) = self.poll.register(stream.evented(), Token(id.0), Ready::readable(), PollOpt::edge()) {
+                        warn!(conn:% = id, peer:% = peer_addr; "Dropping a connection, failed to watch it: {}", e);
+                        continue;
+                    }
+
+                    self.connections.insert(id, Connection::new(id, stream, peer_addr, true));
+                    debug!(conn:% = id, peer:% = peer_addr; "Accepted a connection");
+
+                    events.push(NetworkEvent::Connected(id));
+                }
+
+                continue;
+            }
+
+            let id = ConnId(event.token().0);
+            if let Some(connection) = self.connections.get_mut(&id) {
+                if event.readiness().is_readable() {
+                    connection.receive();
+                }
+
+                if event.readiness().is_writable() {
+                    connection.flush();
+                }
+            }
+        }
+
+        // Process incoming bytes to create packets
+        for (id, connection) in &mut self.connections {
+            connection.decode(*id, &mut events);
+        }
+
+        if let Some(heartbeat) = self.heartbeat {
+            let now: Instant = Instant::now();
+            for (id, connection) in &mut self.connections {
+                if connection.check_heartbeat(&heartbeat, now) {
+                    events.push(NetworkEvent::TimedOut(*id));
+                }
+            }
+        }
+
+        // Remove any disconnected clients
+        let finished: Vec<ConnId> = self.connections.iter()
+            .filter(|&(_, c)| c.is_finished())
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in finished {
+            if let Some(connection) = self.connections.remove(&id) {
+                debug!(conn:% = id, peer:% = connection.peer_addr; "Connection closed");
+                self.poll.deregister(connection.stream.evented())?;
+            }
+
+            events.push(NetworkEvent::Disconnected(id));
+        }
+
+        Ok(events)
+    }
+
+    pub fn send(&mut self, id: ConnId, packet: &Message) -> Result<(), Error> {
+        let data: Vec<u8> = serialize_packet(packet)?;
+        if let Some(connection) = self.connections.get_mut(&id) {
+            connection.outgoing.push_bytes(data);
+        }
+
+        Ok(())
+    }
+
+    // Sends to every connection in `ids`, serializing the packet only once
+    pub fn multicast<'a, I: IntoIterator<Item = &'a ConnId>>(&mut self, ids: I, packet: &Message) -> Result<(), Error> {
+        let data: Vec<u8> = serialize_packet(packet)?;
+        for id in ids {
+            if let Some(connection) = self.connections.get_mut(id) {
+                connection.outgoing.push_bytes(data.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn broadcast(&mut self, packet: &Message) -> Result<(), Error> {
+        let ids: Vec<ConnId> = self.connection_ids();
+        self.multicast(&ids, packet)
+    }
+
+    // Stops reading from the connection and drops it once everything already queued has been sent
+    pub fn close(&mut self, id: ConnId) {
+        if let Some(connection) = self.connections.get_mut(&id) {
+            connection.buffer.clear();
+            connection.is_closing = true;
+        }
+    }
+
+    // Drops the connection on the next poll without sending anything else
+    pub fn disconnect(&mut self, id: ConnId) {
+        if let Some(connection) = self.connections.get_mut(&id) {
+            connection.outgoing.clear();
+            connection.is_disconnected = true;
+        }
+    }
+
+    pub fn connection_ids(&self) -> Vec<ConnId> {
+        self.connections.keys().cloned().collect()
+    }
+
+    pub fn connection_count(&self) -> usize {
+        self.connections.len()
+    }
+
+    pub fn peer_addr(&self, id: ConnId) -> Option<SocketAddr> {
+        self.connections.get(&id).map(|c| c.peer_addr)
+    }
+
+    pub fn queued_bytes(&self, id: ConnId) -> usize {
+        self.connections.get(&id).map_or(0, |c| c.outgoing.queued_bytes())
+    }
+}
+
+pub struct Client {
+    poll: Poll,
+    events: Events,
+    connection: Connection,
+    heartbeat: Option<Heartbeat>
+}
+
+impl Client {
+    // Starts connecting in the background. A Connected event is produced once the connection is established.
+    pub fn connect(addr: &SocketAddr) -> Result<Client, Error> {
+        let socket = TcpStream::connect(addr)?;
+        Client::connect_with(addr, Stream::Plain(socket))
+    }
+
+    // Like `connect`, but wraps the connection in TLS. The server's certificate must be valid for `server_name`,
+    // unless `config` pins a fingerprint instead.
+    pub fn connect_tls(addr: &SocketAddr, server_name: &str, config: Arc<ClientConfig>) -> Result<Client, Error> {
+        let socket = TcpStream::connect(addr)?;
+        Client::connect_with(addr, Stream::client(socket, server_name, config)?)
+    }
+
+    fn connect_with(addr: &SocketAddr, stream: Stream) -> Result<Client, Error> {
+        let poll = Poll::new()?;
+        poll.register(stream.evented(), Token(SERVER_CONN.0), Ready::readable() | Ready::writable(), PollOpt::edge())?;
+
+        Ok(Client {
+            poll,
+            events: Events::with_capacity(EVENT_CAPACITY),
+            connection: Connection::new(SERVER_CONN, stream, *addr, false),
+            heartbeat: None
+        })
+    }
+
+    // Pings the server when it goes quiet, and disconnects if it stops answering. Off (None) by default.
+    pub fn set_heartbeat(&mut self, heartbeat: Option<Heartbeat>) {
+        self.heartbeat = heartbeat;
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_connected && !self.connection.is_finished()
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        self.connection.is_finished()
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.connection.peer_addr
+    }
+
+    pub fn queued_bytes(&self) -> usize {
+        self.connection.outgoing.queued_bytes()
+    }
+
+    // Waits for network activity and returns everything that happened, in order
+    pub fn poll(&mut self, timeout: Option<Duration>) -> Result<Vec<NetworkEvent>, Error> {
+        let mut events: Vec<NetworkEvent> = Vec::new();
+        if self.connection.is_finished() {
+            return Ok(events);
+        }
+
+        let deadline: Option<Instant> = match self.heartbeat {
+            Some(ref heartbeat) if self.connection.is_connected => Some(self.connection.heartbeat_deadline(heartbeat)),
+            _ => None
+        };
+
+        self.poll.reregister(self.connection.stream.evented(), Token(SERVER_CONN.0), self.connection.interest(), PollOpt::edge())?;
+        self.poll.poll(&mut self.events, poll_timeout(timeout, deadline))?;
+
+        for event in self.events.iter() {
+            if !self.connection.is_connected {
+                // A failed connect shows up as an error on the socket
+                match self.connection.stream.take_error()? {
+                    Some(e) => return Err(Error::Io(e)),
+                    None => {
+                        debug!(conn:% = SERVER_CONN, peer:% = self.connection.peer_addr; "Connected");
+                        self.connection.is_connected = true;
+                        self.connection.last_received = Instant::now();
+                        events.push(NetworkEvent::Connected(SERVER_CONN));
+                    }
+                }
+            }
+
+            if event.readiness().is_readable() {
+                self.connection.receive();
+            }
+
+            if event.readiness().is_writable() {
+                self.connection.flush();
+            }
+        }
+
+        // Process incoming bytes to create packets
+        self.connection.decode(SERVER_CONN, &mut events);
+
+        if let Some(heartbeat) = self.heartbeat {
+            if self.connection.check_heartbeat(&heartbeat, Instant::now()) {
+                events.push(NetworkEvent::TimedOut(SERVER_CONN));
+            }
+        }
+
+        if self.connection.is_finished() {
+            self.poll.deregister(self.connection.stream.evented())?;
+            events.push(NetworkEvent::Disconnected(SERVER_CONN));
+        }
+
+        Ok(events)
+    }
+
+    // Packets sent before the connection is established are queued until it is
+    pub fn send(&mut self, packet: &Message) -> Result<(), Error> {
+        self.connection.outgoing.push(packet)
+    }
+
+    pub fn close(&mut self) {
+        self.connection.buffer.clear();
+        self.connection.is_closing = true;
+    }
+}
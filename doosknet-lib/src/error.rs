@@ -0,0 +1,101 @@
+use std::error;
+use std::fmt;
+use std::io;
+use bincode;
+use rustls;
+
+#[derive(Debug)]
+pub enum DecodeError {
+    // Not enough bytes have arrived yet to decode the next packet
+    Incomplete,
+    // The header announced a body larger than MAX_PACKET_BODY_SIZE. The stream can't be recovered.
+    FrameTooLarge(usize),
+    // The frame was complete but its body could not be decoded. The frame has been discarded.
+    Malformed(String),
+    // The frame was written by a peer speaking another protocol version. The frame has been discarded.
+    UnsupportedVersion(u16)
+}
+
+impl DecodeError {
+    // Whether the connection is still usable after this error
+    pub fn is_recoverable(&self) -> bool {
+        match *self {
+            DecodeError::Incomplete | DecodeError::Malformed(_) => true,
+            DecodeError::FrameTooLarge(_) | DecodeError::UnsupportedVersion(_) => false
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Incomplete => write!(f, "Need more bytes to decode a packet"),
+            DecodeError::FrameTooLarge(size) => write!(f, "Packet body too large! {} >= {}", size, ::MAX_PACKET_BODY_SIZE),
+            DecodeError::Malformed(ref reason) => write!(f, "Malformed packet body: {}", reason),
+            DecodeError::UnsupportedVersion(version) => write!(f, "Unsupported protocol version! {} != {}", version, ::PROTOCOL_VERSION)
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Decode(DecodeError),
+    Encode(bincode::Error),
+    // An outgoing packet would exceed MAX_PACKET_BODY_SIZE once serialized
+    PacketTooLarge(usize),
+    // The receive buffer reached its maximum capacity. Decode some packets before reading again.
+    BufferFull,
+    // Bad TLS configuration, certificates or keys
+    Tls(String)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::Decode(ref e) => write!(f, "Decode error: {}", e),
+            Error::Encode(ref e) => write!(f, "Encode error: {}", e),
+            Error::PacketTooLarge(size) => write!(f, "Packet body too large to send! {} >= {}", size, ::MAX_PACKET_BODY_SIZE),
+            Error::BufferFull => write!(f, "Receive buffer is full"),
+            Error::Tls(ref reason) => write!(f, "TLS error: {}", reason)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            Error::Decode(ref e) => Some(e),
+            Error::Encode(ref e) => Some(e),
+            Error::PacketTooLarge(_) | Error::BufferFull | Error::Tls(_) => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(e: DecodeError) -> Self {
+        Error::Decode(e)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Self {
+        Error::Encode(e)
+    }
+}
+
+impl From<rustls::Error> for Error {
+    fn from(e: rustls::Error) -> Self {
+        Error::Tls(e.to_string())
+    }
+}
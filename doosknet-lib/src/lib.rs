@@ -1,134 +1,247 @@
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
+extern crate log;
 
 extern crate mio;
 extern crate serde;
 extern crate bincode;
 extern crate byteorder;
+extern crate rustls;
+extern crate ring;
+extern crate net2;
+
+pub mod error;
+pub mod buffer;
+pub mod queue;
+pub mod endpoint;
+pub mod tls;
+pub mod pipe;
+pub mod transport;
+mod stream;
 
-use std::io::{self, Write, Error, ErrorKind, Cursor};
 use std::str;
 use mio::*;
-use mio::net::TcpStream;
-use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{ByteOrder, LittleEndian, NetworkEndian, WriteBytesExt};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+pub use error::{Error, DecodeError};
+pub use buffer::{NetworkBuffer, DEFAULT_BUFFER_CAPACITY, MAX_BUFFER_SIZE};
+pub use queue::SendQueue;
+pub use pipe::{pipe, PipeStream};
+pub use transport::Transport;
+pub use endpoint::{Server, Client, ConnId, NetworkEvent, Heartbeat, Waker, SERVER_CONN};
 
 pub const LOCAL_TOKEN: Token = Token(0);
-pub const MAX_BUFFER_SIZE: usize = 1024;
 pub const PACKET_HEADER_SIZE: usize = 8;
 pub const MAX_PACKET_BODY_SIZE: usize = 256;
 pub const MAX_PACKET_SIZE: usize = PACKET_HEADER_SIZE + MAX_PACKET_BODY_SIZE;
 
-#[derive(Serialize, Deserialize, Clone)]
-pub struct Packet {
-    pub sender: String,
-    pub message: String
+// Bump this whenever the layout of Message changes
+pub const PROTOCOL_VERSION: u16 = 8;
+
+// Hello, Welcome and Rejected, the first variants of Message
+const HANDSHAKE_VARIANTS: u32 = 3;
+
+// Optional protocol features this build understands, exchanged during the handshake
+pub const CAPABILITIES: &[&str] = &["chat"];
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Message {
+    // Handshake. These variants must keep their position and fields: they are decoded whatever version
+    // the packet header carries, so peers on different versions can still tell each other they don't match.
+    Hello { version: u16, capabilities: Vec<String>, username: String },
+    Welcome { version: u16, capabilities: Vec<String>, username: String, server_name: String, motd: String },
+    Rejected { reason: String },
+    // The nickname in a Hello was refused. The connection stays open so the client can try another one.
+    NicknameRejected { username: String, reason: String },
+    // The nickname in a Hello belongs to an account. Answer with a Login to use it. `reason` says why a Login failed.
+    AuthRequired { username: String, reason: String },
+    Login { username: String, password: String },
+
+    // The server fills in `sender` with the nickname of the connection that sent the message
+    Chat { channel: String, sender: String, message: String },
+    // "/me waves" is shown as "* sender waves"
+    Action { channel: String, sender: String, message: String },
+    // A private message. The server delivers it to `target` and echoes it back to the sender.
+    DirectMessage { sender: String, target: String, message: String },
+    // Someone joined or left a channel we are in. We get our own too.
+    Join { channel: String, username: String },
+    Leave { channel: String, username: String },
+    // Sent to us after we join a channel, or /join one we are already in
+    Joined { channel: String, topic: String },
+    Topic { channel: String, username: String, topic: String },
+    NickChange { old_username: String, new_username: String },
+    Notice { message: String },
+    Error { message: String },
+    CommandReply { message: String },
+    // Asks for up to `count` channel messages older than message `before` (0 for the newest ones)
+    HistoryRequest { channel: String, before: u64, count: u16 },
+    // One stored channel message, oldest first. Sent on join and in answer to a HistoryRequest.
+    HistoryEntry { channel: String, id: u64, timestamp: u64, sender: String, message: String, is_action: bool },
+    // Ends a batch of HistoryEntry packets. `has_more` is false once the start of the history was reached.
+    HistoryEnd { channel: String, has_more: bool },
+    // Heartbeats. The endpoints answer and swallow these themselves, see endpoint::Heartbeat.
+    Ping(u64),
+    Pong(u64)
 }
 
-impl Packet {
-    pub fn new(sender: &str, message: &str) -> Self {
-        let sender = String::from(sender);
-        let message = String::from(message);
-
-        Packet {
-            sender,
-            message
+impl Message {
+    pub fn hello(username: &str) -> Self {
+        Message::Hello {
+            version: PROTOCOL_VERSION,
+            capabilities: CAPABILITIES.iter().map(|c| String::from(*c)).collect(),
+            username: String::from(username)
         }
     }
-}
 
-pub fn serialize_packet(packet: Packet) -> Vec<u8> {
-    // Body
-    let mut body_data: Vec<u8> = bincode::serialize(&packet).unwrap();
-
-    // Header
-    let mut data: Vec<u8> = Vec::new();
-    data.write_u64::<NetworkEndian>(body_data.len() as u64).unwrap();
+    pub fn welcome(username: &str, capabilities: Vec<String>, server_name: &str, motd: &str) -> Self {
+        Message::Welcome {
+            version: PROTOCOL_VERSION,
+            capabilities,
+            username: String::from(username),
+            server_name: String::from(server_name),
+            motd: String::from(motd)
+        }
+    }
 
-    // Combine the body and header
-    data.append(&mut body_data);
+    pub fn rejected(reason: &str) -> Self {
+        Message::Rejected {
+            reason: String::from(reason)
+        }
+    }
 
-    data
-}
+    pub fn nickname_rejected(username: &str, reason: &str) -> Self {
+        Message::NicknameRejected {
+            username: String::from(username),
+            reason: String::from(reason)
+        }
+    }
 
-pub fn deserialize_packet(buffer: &mut NetworkBuffer) -> Option<Packet> {
-    // Ensure there is enough data for a packet header
-    if buffer.offset < PACKET_HEADER_SIZE {
-        return None;
+    pub fn auth_required(username: &str, reason: &str) -> Self {
+        Message::AuthRequired {
+            username: String::from(username),
+            reason: String::from(reason)
+        }
     }
 
-    let body_size: usize;
-    let packet: Option<Packet>;
-    {
-        let mut reader = Cursor::new(&buffer.data[..]);
+    pub fn login(username: &str, password: &str) -> Self {
+        Message::Login {
+            username: String::from(username),
+            password: String::from(password)
+        }
+    }
 
-        // Read header
-        body_size = reader.read_u64::<NetworkEndian>().unwrap() as usize;
-        if body_size >= MAX_PACKET_BODY_SIZE {
-            eprintln!("Packet body too large! {} >= {}", body_size, MAX_PACKET_BODY_SIZE);
-            return None;
+    pub fn chat(channel: &str, sender: &str, message: &str) -> Self {
+        Message::Chat {
+            channel: String::from(channel),
+            sender: String::from(sender),
+            message: String::from(message)
         }
+    }
 
-        // Ensure there is enough data for the rest of the packet
-        if buffer.offset < (body_size + PACKET_HEADER_SIZE) {
-            return None;
+    pub fn action(channel: &str, sender: &str, message: &str) -> Self {
+        Message::Action {
+            channel: String::from(channel),
+            sender: String::from(sender),
+            message: String::from(message)
         }
+    }
 
-        let deserialized: Packet = bincode::deserialize(&buffer.data[PACKET_HEADER_SIZE..]).unwrap();
+    pub fn direct_message(sender: &str, target: &str, message: &str) -> Self {
+        Message::DirectMessage {
+            sender: String::from(sender),
+            target: String::from(target),
+            message: String::from(message)
+        }
+    }
 
-        packet = Some(deserialized);
+    pub fn notice(message: &str) -> Self {
+        Message::Notice {
+            message: String::from(message)
+        }
     }
 
-    // Remove the packet from the front of the buffer, and shift the rest of the buffer forward.
-    buffer.drain(body_size + PACKET_HEADER_SIZE);
+    pub fn error(message: &str) -> Self {
+        Message::Error {
+            message: String::from(message)
+        }
+    }
 
-    packet
+    pub fn command_reply(message: &str) -> Self {
+        Message::CommandReply {
+            message: String::from(message)
+        }
+    }
 }
 
-pub struct NetworkBuffer {
-    pub data: [u8; MAX_BUFFER_SIZE],
-    pub offset: usize
+// Returns the capabilities requested by a peer that this build also supports
+pub fn negotiate_capabilities(requested: &[String]) -> Vec<String> {
+    requested.iter()
+        .filter(|c| CAPABILITIES.contains(&c.as_str()))
+        .cloned()
+        .collect()
 }
 
-impl NetworkBuffer {
-    pub fn new() -> Self {
-        NetworkBuffer {
-            data: [0; MAX_BUFFER_SIZE],
-            offset: 0
-        }
+// Header layout:
+// [u16 protocol version][u16 reserved][u32 body size]
+pub fn serialize_packet<T: Serialize>(packet: &T) -> Result<Vec<u8>, Error> {
+    // Body
+    let mut body_data: Vec<u8> = bincode::serialize(packet)?;
+    if body_data.len() >= MAX_PACKET_BODY_SIZE {
+        return Err(Error::PacketTooLarge(body_data.len()));
     }
 
-    pub fn drain(&mut self, count: usize) {
-        unsafe {
-            use std::ptr;
-            ptr::copy(self.data.as_ptr().offset(count as isize), self.data.as_mut_ptr(), self.offset - count);
-        }
+    // Header
+    let mut data: Vec<u8> = Vec::with_capacity(PACKET_HEADER_SIZE + body_data.len());
+    data.write_u16::<NetworkEndian>(PROTOCOL_VERSION)?;
+    data.write_u16::<NetworkEndian>(0)?;
+    data.write_u32::<NetworkEndian>(body_data.len() as u32)?;
+
+    // Combine the body and header
+    data.append(&mut body_data);
+
+    Ok(data)
+}
 
-        self.offset -= count;
+pub fn deserialize_packet<T: DeserializeOwned>(buffer: &mut NetworkBuffer) -> Result<T, DecodeError> {
+    // Ensure there is enough data for a packet header
+    if buffer.len() < PACKET_HEADER_SIZE {
+        return Err(DecodeError::Incomplete);
     }
 
-    pub fn clear(&mut self) {
-        self.data = [0; MAX_BUFFER_SIZE];
-        self.offset = 0;
+    // Read header
+    let version: u16 = NetworkEndian::read_u16(&buffer.as_slice()[0..2]);
+    let body_size: usize = NetworkEndian::read_u32(&buffer.as_slice()[4..8]) as usize;
+    if body_size >= MAX_PACKET_BODY_SIZE {
+        return Err(DecodeError::FrameTooLarge(body_size));
     }
-}
 
-pub fn send_bytes(socket: &mut TcpStream, buffer: &[u8]) -> Result<usize, io::Error> {
-    let mut len = buffer.len();
-    if len == 0 {
-        return Err(Error::new(ErrorKind::InvalidData, "Buffer is empty!"));
-    }
-
-    // Keep sending until we've sent the entire buffer
-    while len > 0 {
-        match socket.write(buffer) {
-            Ok(sent_bytes) => {
-                len -= sent_bytes;
-            },
-            Err(e) => {
-                return Err(e);
-            }
-        }
+    // Ensure there is enough data for the rest of the packet
+    if buffer.len() < (body_size + PACKET_HEADER_SIZE) {
+        return Err(DecodeError::Incomplete);
+    }
+
+    let body = &buffer.as_slice()[PACKET_HEADER_SIZE..(PACKET_HEADER_SIZE + body_size)];
+    let packet: Result<T, DecodeError> = if version == PROTOCOL_VERSION {
+        bincode::deserialize(body).map_err(|e| DecodeError::Malformed(e.to_string()))
+    }
+    else if is_handshake(body) {
+        // Decodes the same in every version, so the peer can find out why it is being turned away
+        bincode::deserialize(body).map_err(|_| DecodeError::UnsupportedVersion(version))
     }
+    else {
+        Err(DecodeError::UnsupportedVersion(version))
+    };
 
-    Ok(buffer.len())
-}
\ No newline at end of file
+    // Remove the packet from the front of the buffer
+    buffer.drain(body_size + PACKET_HEADER_SIZE);
+
+    packet
+}
+
+// Whether a packet body holds one of the handshake variants. bincode starts an enum with its variant index.
+fn is_handshake(body: &[u8]) -> bool {
+    body.len() >= 4 && LittleEndian::read_u32(&body[0..4]) < HANDSHAKE_VARIANTS
+}
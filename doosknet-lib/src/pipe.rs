@@ -0,0 +1,108 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use mio::{Evented, Poll, PollOpt, Ready, Registration, SetReadiness, Token};
+
+// Bytes travelling one way through a pipe, and the readiness of the end that reads them
+struct PipeBuffer {
+    bytes: VecDeque<u8>,
+    is_closed: bool,
+    readiness: SetReadiness
+}
+
+// One end of an in-process connection, made by `pipe`. Works with mio like a socket does:
+// it is always writable, and readable whenever the other end has written something or gone away.
+// Writes never fill it up. Dropping either end closes the pipe.
+pub struct PipeStream {
+    incoming: Arc<Mutex<PipeBuffer>>,
+    outgoing: Arc<Mutex<PipeBuffer>>,
+    registration: Registration
+}
+
+// Two connected ends. What is written to one is read from the other.
+pub fn pipe() -> (PipeStream, PipeStream) {
+    let (registration_a, readiness_a) = Registration::new2();
+    let (registration_b, readiness_b) = Registration::new2();
+
+    let _ = readiness_a.set_readiness(Ready::writable());
+    let _ = readiness_b.set_readiness(Ready::writable());
+
+    let to_a = Arc::new(Mutex::new(PipeBuffer { bytes: VecDeque::new(), is_closed: false, readiness: readiness_a }));
+    let to_b = Arc::new(Mutex::new(PipeBuffer { bytes: VecDeque::new(), is_closed: false, readiness: readiness_b }));
+
+    let a = PipeStream { incoming: to_a.clone(), outgoing: to_b.clone(), registration: registration_a };
+    let b = PipeStream { incoming: to_b, outgoing: to_a, registration: registration_b };
+
+    (a, b)
+}
+
+// The buffers are always left consistent, so a panic elsewhere doesn't stop them being used
+fn lock(buffer: &Mutex<PipeBuffer>) -> MutexGuard<'_, PipeBuffer> {
+    buffer.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Read for PipeStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut incoming = lock(&self.incoming);
+
+        if incoming.bytes.is_empty() {
+            if incoming.is_closed {
+                return Ok(0);
+            }
+
+            // Nothing until the other end writes again, which makes us readable
+            incoming.readiness.set_readiness(Ready::writable())?;
+            return Err(io::Error::new(io::ErrorKind::WouldBlock, "Pipe is empty"));
+        }
+
+        let size: usize = buf.len().min(incoming.bytes.len());
+        for (dest, byte) in buf.iter_mut().zip(incoming.bytes.drain(..size)) {
+            *dest = byte;
+        }
+
+        Ok(size)
+    }
+}
+
+impl Write for PipeStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut outgoing = lock(&self.outgoing);
+        if outgoing.is_closed {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "Pipe is closed"));
+        }
+
+        outgoing.bytes.extend(buf);
+        outgoing.readiness.set_readiness(Ready::readable() | Ready::writable())?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for PipeStream {
+    fn drop(&mut self) {
+        lock(&self.incoming).is_closed = true;
+
+        // The other end reads whatever is left, then sees the pipe close
+        let mut outgoing = lock(&self.outgoing);
+        outgoing.is_closed = true;
+        let _ = outgoing.readiness.set_readiness(Ready::readable() | Ready::writable());
+    }
+}
+
+impl Evented for PipeStream {
+    fn register(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
+        poll.register(&self.registration, token, interest, opts)
+    }
+
+    fn reregister(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
+        poll.reregister(&self.registration, token, interest, opts)
+    }
+
+    fn deregister(&self, poll: &Poll) -> io::Result<()> {
+        poll.deregister(&self.registration)
+    }
+}
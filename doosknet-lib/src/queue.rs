@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use serde::Serialize;
+use error::Error;
+use serialize_packet;
+
+// Serialized frames waiting to be written to a non-blocking socket.
+// A frame stays at the front of the queue until every byte of it has been written,
+// so partial writes and WouldBlock simply resume from the cursor on the next writable event.
+pub struct SendQueue {
+    frames: VecDeque<Vec<u8>>,
+    cursor: usize,
+    queued_bytes: usize,
+    sent_bytes: u64,
+    sent_frames: u64
+}
+
+impl SendQueue {
+    pub fn new() -> Self {
+        SendQueue {
+            frames: VecDeque::new(),
+            cursor: 0,
+            queued_bytes: 0,
+            sent_bytes: 0,
+            sent_frames: 0
+        }
+    }
+
+    pub fn push<T: Serialize>(&mut self, packet: &T) -> Result<(), Error> {
+        let data: Vec<u8> = serialize_packet(packet)?;
+        self.push_bytes(data);
+
+        Ok(())
+    }
+
+    pub fn push_bytes(&mut self, data: Vec<u8>) {
+        if data.is_empty() {
+            return;
+        }
+
+        self.queued_bytes += data.len();
+        self.frames.push_back(data);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // Bytes still waiting to be written, including the unwritten part of a partially sent frame
+    pub fn queued_bytes(&self) -> usize {
+        self.queued_bytes
+    }
+
+    pub fn queued_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    // Totals over the lifetime of the queue
+    pub fn sent_bytes(&self) -> u64 {
+        self.sent_bytes
+    }
+
+    pub fn sent_frames(&self) -> u64 {
+        self.sent_frames
+    }
+
+    // Drops everything that hasn't been written yet
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.cursor = 0;
+        self.queued_bytes = 0;
+    }
+
+    // Writes as much as the writer will take, returning the number of bytes written.
+    // Stops without error when the writer would block; call again on the next writable event.
+    pub fn flush<W: Write>(&mut self, writer: &mut W) -> Result<usize, Error> {
+        let mut written: usize = 0;
+
+        while let Some(frame) = self.frames.front() {
+            match writer.write(&frame[self.cursor..]) {
+                Ok(0) => {
+                    return Err(Error::Io(io::Error::new(io::ErrorKind::WriteZero, "Socket accepted no bytes")));
+                },
+                Ok(sent_bytes) => {
+                    written += sent_bytes;
+                    self.cursor += sent_bytes;
+                    self.queued_bytes -= sent_bytes;
+                    self.sent_bytes += sent_bytes as u64;
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Error::Io(e))
+            }
+
+            if self.cursor == frame.len() {
+                self.frames.pop_front();
+                self.cursor = 0;
+                self.sent_frames += 1;
+            }
+        }
+
+        Ok(written)
+    }
+}
+
+impl Default for SendQueue {
+    fn default() -> Self {
+        SendQueue::new()
+    }
+}
@@ -0,0 +1,154 @@
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use mio::Evented;
+use mio::net::TcpStream;
+use rustls;
+use rustls::{ClientConnection, ServerConnection};
+use rustls::pki_types::ServerName;
+use error::Error;
+use pipe::PipeStream;
+use tls::{ClientConfig, ServerConfig};
+
+// The bytes under a Server or Client connection: a TCP socket, optionally wrapped in a TLS session,
+// or one end of an in-process pipe. Reads and writes never block; they report WouldBlock just like
+// the bare socket would.
+pub enum Stream {
+    Plain(TcpStream),
+    Tls(TcpStream, Box<rustls::Connection>),
+    Pipe(PipeStream)
+}
+
+impl Stream {
+    pub fn server(socket: TcpStream, config: Arc<ServerConfig>) -> Result<Stream, Error> {
+        let session = ServerConnection::new(config)?;
+        Ok(Stream::Tls(socket, Box::new(rustls::Connection::Server(session))))
+    }
+
+    pub fn client(socket: TcpStream, server_name: &str, config: Arc<ClientConfig>) -> Result<Stream, Error> {
+        let server_name = ServerName::try_from(String::from(server_name))
+            .map_err(|e| Error::Tls(format!("Invalid server name {}: {}", server_name, e)))?;
+
+        let session = ClientConnection::new(config, server_name)?;
+        Ok(Stream::Tls(socket, Box::new(rustls::Connection::Client(session))))
+    }
+
+    // What to register with a mio Poll
+    pub fn evented(&self) -> &dyn Evented {
+        match *self {
+            Stream::Plain(ref socket) => socket,
+            Stream::Tls(ref socket, _) => socket,
+            Stream::Pipe(ref pipe) => pipe
+        }
+    }
+
+    // The error a failed connect left on the socket. Pipes are connected from the start.
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        match *self {
+            Stream::Plain(ref socket) => socket.take_error(),
+            Stream::Tls(ref socket, _) => socket.take_error(),
+            Stream::Pipe(_) => Ok(None)
+        }
+    }
+
+    // True when encrypted bytes are waiting to go out, even if the plaintext was already accepted
+    pub fn wants_write(&self) -> bool {
+        match *self {
+            Stream::Tls(_, ref session) => session.wants_write(),
+            _ => false
+        }
+    }
+
+    // Pushes pending TLS records onto the socket
+    pub fn write_pending(&mut self) -> io::Result<()> {
+        if let Stream::Tls(ref mut socket, ref mut session) = *self {
+            while session.wants_write() {
+                session.write_tls(socket)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Tells a TLS peer we are done. The close_notify goes out with the next `write_pending`.
+    pub fn send_close_notify(&mut self) {
+        if let Stream::Tls(_, ref mut session) = *self {
+            session.send_close_notify();
+        }
+    }
+}
+
+fn ignore_would_block(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+        result => result
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (socket, session) = match *self {
+            Stream::Plain(ref mut socket) => return socket.read(buf),
+            Stream::Pipe(ref mut pipe) => return pipe.read(buf),
+            Stream::Tls(ref mut socket, ref mut session) => (socket, session)
+        };
+
+        loop {
+            // Hand out anything that has already been decrypted
+            match session.reader().read(buf) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+                result => return result
+            }
+
+            // Then pull more records off the socket
+            if session.read_tls(socket)? == 0 {
+                return Ok(0);
+            }
+
+            if let Err(e) = session.process_new_packets() {
+                // Try to tell the peer what went wrong before giving up
+                let _ = session.write_tls(socket);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+            }
+
+            // The handshake may need us to respond
+            while session.wants_write() {
+                match session.write_tls(socket) {
+                    Ok(_) => (),
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e)
+                }
+            }
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Plain(ref mut socket) => return socket.write(buf),
+            Stream::Pipe(ref mut pipe) => return pipe.write(buf),
+            Stream::Tls(..) => ()
+        }
+
+        // Make room in the session's buffer first
+        ignore_would_block(self.write_pending())?;
+
+        let written: usize = match *self {
+            Stream::Tls(_, ref mut session) => session.writer().write(buf)?,
+            _ => unreachable!()
+        };
+
+        if written == 0 && !buf.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::WouldBlock, "TLS send buffer is full"));
+        }
+
+        ignore_would_block(self.write_pending())?;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_pending()
+    }
+}
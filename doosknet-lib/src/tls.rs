@@ -0,0 +1,127 @@
+use std::path::Path;
+use std::sync::Arc;
+use ring::digest;
+use rustls;
+use rustls::{RootCertStore, DigitallySignedStruct, SignatureScheme};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::pki_types::pem::PemObject;
+use error::Error;
+
+pub use rustls::{ClientConfig, ServerConfig};
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+fn load_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let certs: Vec<CertificateDer<'static>> = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect())
+        .map_err(|e| Error::Tls(format!("Failed to read certificates from {}: {}", path.display(), e)))?;
+
+    if certs.is_empty() {
+        return Err(Error::Tls(format!("No certificates found in {}", path.display())));
+    }
+
+    Ok(certs)
+}
+
+// Server side configuration from a PEM certificate chain and private key
+pub fn load_server_config(cert_path: &Path, key_path: &Path) -> Result<Arc<ServerConfig>, Error> {
+    let certs = load_certificates(cert_path)?;
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| Error::Tls(format!("Failed to read private key from {}: {}", key_path.display(), e)))?;
+
+    let config = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+
+    Ok(Arc::new(config))
+}
+
+// Client side configuration that trusts servers signed by the CA certificates in `ca_path`
+pub fn load_client_config(ca_path: &Path) -> Result<Arc<ClientConfig>, Error> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certificates(ca_path)? {
+        roots.add(cert)?;
+    }
+
+    let config = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    Ok(Arc::new(config))
+}
+
+// Client side configuration that trusts exactly one server certificate, identified by its SHA-256 fingerprint.
+// Useful for self-signed servers. The fingerprint is hex, with or without ':' separators.
+pub fn pinned_client_config(fingerprint: &str) -> Result<Arc<ClientConfig>, Error> {
+    let fingerprint: Vec<u8> = parse_fingerprint(fingerprint)?;
+    let provider = provider();
+
+    let config = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(FingerprintVerifier { fingerprint, provider }))
+        .with_no_client_auth();
+
+    Ok(Arc::new(config))
+}
+
+// SHA-256 fingerprint of the first certificate in a PEM file, formatted for `pinned_client_config`
+pub fn certificate_fingerprint(cert_path: &Path) -> Result<String, Error> {
+    let certs = load_certificates(cert_path)?;
+    Ok(format_fingerprint(digest::digest(&digest::SHA256, certs[0].as_ref()).as_ref()))
+}
+
+fn format_fingerprint(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<String>>()
+        .join(":")
+}
+
+fn parse_fingerprint(fingerprint: &str) -> Result<Vec<u8>, Error> {
+    let hex: String = fingerprint.chars().filter(|c| *c != ':').collect();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(Error::Tls(format!("Invalid SHA-256 fingerprint {}", fingerprint)));
+    }
+
+    (0..32)
+        .map(|i| u8::from_str_radix(&hex[(i * 2)..(i * 2 + 2)], 16)
+            .map_err(|_| Error::Tls(format!("Invalid SHA-256 fingerprint {}", fingerprint))))
+        .collect()
+}
+
+#[derive(Debug)]
+struct FingerprintVerifier {
+    fingerprint: Vec<u8>,
+    provider: Arc<CryptoProvider>
+}
+
+impl ServerCertVerifier for FingerprintVerifier {
+    fn verify_server_cert(&self, end_entity: &CertificateDer, _intermediates: &[CertificateDer], _server_name: &ServerName,
+                          _ocsp_response: &[u8], _now: UnixTime) -> Result<ServerCertVerified, rustls::Error> {
+        if digest::digest(&digest::SHA256, end_entity.as_ref()).as_ref() == &self.fingerprint[..] {
+            Ok(ServerCertVerified::assertion())
+        }
+        else {
+            Err(rustls::Error::General(String::from("Server certificate does not match the pinned fingerprint")))
+        }
+    }
+
+    fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
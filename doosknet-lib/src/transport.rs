@@ -0,0 +1,23 @@
+use std::time::Duration;
+use endpoint::{Client, NetworkEvent};
+use error::Error;
+use Message;
+
+// A connection to a server, as far as the code using it is concerned. Client is one, over TCP, TLS or a pipe;
+// anything else that can pass packets back and forth, such as a stand-in for tests, can be too.
+pub trait Transport {
+    // Queues a packet. It goes out on a later poll.
+    fn send(&mut self, packet: &Message) -> Result<(), Error>;
+    // Waits up to `timeout` for something to happen
+    fn poll(&mut self, timeout: Option<Duration>) -> Result<Vec<NetworkEvent>, Error>;
+}
+
+impl Transport for Client {
+    fn send(&mut self, packet: &Message) -> Result<(), Error> {
+        Client::send(self, packet)
+    }
+
+    fn poll(&mut self, timeout: Option<Duration>) -> Result<Vec<NetworkEvent>, Error> {
+        Client::poll(self, timeout)
+    }
+}
@@ -0,0 +1,209 @@
+extern crate doosknet;
+extern crate proptest;
+
+use std::io::{self, Cursor, Read};
+use doosknet::*;
+use proptest::prelude::*;
+
+fn frame(message: &Message) -> Vec<u8> {
+    serialize_packet(message).unwrap()
+}
+
+fn chat(i: usize) -> Message {
+    Message::chat("#general", "Bob", &format!("message number {}", i))
+}
+
+// Reader that hands out its data in fixed-size chunks, like a socket would
+struct ChunkedReader {
+    data: Vec<u8>,
+    position: usize,
+    chunk_size: usize
+}
+
+impl Read for ChunkedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.chunk_size.min(buf.len()).min(self.data.len() - self.position);
+        buf[..count].copy_from_slice(&self.data[self.position..(self.position + count)]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+#[test]
+fn split_frame_waits_for_the_rest() {
+    let data = frame(&chat(0));
+    let mut buffer = NetworkBuffer::new();
+
+    for byte in &data[..(data.len() - 1)] {
+        assert_eq!(buffer.extend_from_slice(&[*byte]), 1);
+        match deserialize_packet::<Message>(&mut buffer) {
+            Err(DecodeError::Incomplete) => (),
+            other => panic!("expected Incomplete, got {:?}", other)
+        }
+    }
+
+    buffer.extend_from_slice(&data[(data.len() - 1)..]);
+    assert_eq!(deserialize_packet::<Message>(&mut buffer).unwrap(), chat(0));
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn many_frames_in_one_read() {
+    let mut data: Vec<u8> = Vec::new();
+    for i in 0..12 {
+        data.extend(frame(&chat(i)));
+    }
+
+    let mut buffer = NetworkBuffer::new();
+    let mut reader = Cursor::new(data.clone());
+    assert_eq!(buffer.read_from(&mut reader).unwrap(), data.len());
+
+    for i in 0..12 {
+        assert_eq!(deserialize_packet::<Message>(&mut buffer).unwrap(), chat(i));
+    }
+
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn frame_fits_once_the_buffer_compacts() {
+    // Smallest buffer allowed, so it can never grow past a single packet
+    let mut buffer = NetworkBuffer::with_limits(0, 0);
+    assert_eq!(buffer.max_capacity(), MAX_PACKET_SIZE);
+
+    let first_message = Message::chat("#general", "Bob", &"a".repeat(120));
+    let second_message = Message::chat("#general", "Jim", &"b".repeat(120));
+    let first = frame(&first_message);
+    let second = frame(&second_message);
+    assert!(first.len() + second.len() > MAX_PACKET_SIZE);
+
+    // Fill the storage so the tail of the second frame only fits once the consumed head is compacted away
+    buffer.extend_from_slice(&first);
+    let head = MAX_PACKET_SIZE - first.len();
+    assert_eq!(buffer.extend_from_slice(&second[..head]), head);
+    assert_eq!(buffer.extend_from_slice(&second[head..]), 0);
+    assert!(buffer.is_full());
+
+    assert_eq!(deserialize_packet::<Message>(&mut buffer).unwrap(), first_message);
+
+    assert_eq!(buffer.extend_from_slice(&second[head..]), second.len() - head);
+    assert_eq!(buffer.capacity(), MAX_PACKET_SIZE);
+    assert_eq!(deserialize_packet::<Message>(&mut buffer).unwrap(), second_message);
+}
+
+#[test]
+fn full_buffer_is_not_mistaken_for_eof() {
+    let mut buffer = NetworkBuffer::with_limits(0, 0);
+    let mut reader = Cursor::new(vec![0u8; MAX_PACKET_SIZE * 2]);
+
+    let mut total: usize = 0;
+    loop {
+        match buffer.read_from(&mut reader) {
+            Ok(0) => panic!("full buffer reported as EOF"),
+            Ok(read_bytes) => total += read_bytes,
+            Err(Error::BufferFull) => break,
+            Err(e) => panic!("unexpected error {}", e)
+        }
+    }
+
+    assert_eq!(total, MAX_PACKET_SIZE);
+    assert!(buffer.is_full());
+
+    buffer.drain(10);
+    assert_eq!(buffer.read_from(&mut reader).unwrap(), 10);
+}
+
+#[test]
+fn eof_reads_zero_bytes() {
+    let mut buffer = NetworkBuffer::new();
+    let mut reader = Cursor::new(Vec::new());
+    assert_eq!(buffer.read_from(&mut reader).unwrap(), 0);
+}
+
+#[test]
+fn buffer_grows_up_to_its_limit() {
+    let mut buffer = NetworkBuffer::with_limits(16, 4096);
+    assert_eq!(buffer.extend_from_slice(&[1u8; 5000]), 4096);
+    assert_eq!(buffer.capacity(), 4096);
+    assert!(buffer.is_full());
+}
+
+#[test]
+fn oversized_frame_is_rejected() {
+    let mut buffer = NetworkBuffer::new();
+    buffer.extend_from_slice(&[0, 1, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+
+    match deserialize_packet::<Message>(&mut buffer) {
+        Err(DecodeError::FrameTooLarge(_)) => (),
+        other => panic!("expected FrameTooLarge, got {:?}", other)
+    }
+}
+
+// A frame as a peer on another protocol version would send it
+fn frame_from_version(message: &Message, version: u16) -> Vec<u8> {
+    let mut data = frame(message);
+    data[0] = (version >> 8) as u8;
+    data[1] = version as u8;
+    data
+}
+
+#[test]
+fn handshake_frames_decode_from_any_version() {
+    let mut buffer = NetworkBuffer::new();
+    buffer.extend_from_slice(&frame_from_version(&Message::rejected("Old client"), PROTOCOL_VERSION + 1));
+    buffer.extend_from_slice(&frame_from_version(&Message::hello("Jeb"), PROTOCOL_VERSION - 1));
+
+    assert_eq!(deserialize_packet::<Message>(&mut buffer).unwrap(), Message::rejected("Old client"));
+    assert_eq!(deserialize_packet::<Message>(&mut buffer).unwrap(), Message::hello("Jeb"));
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn other_frames_from_another_version_are_unsupported() {
+    let mut buffer = NetworkBuffer::new();
+    buffer.extend_from_slice(&frame_from_version(&chat(0), PROTOCOL_VERSION + 1));
+
+    match deserialize_packet::<Message>(&mut buffer) {
+        Err(DecodeError::UnsupportedVersion(version)) => assert_eq!(version, PROTOCOL_VERSION + 1),
+        other => panic!("expected UnsupportedVersion, got {:?}", other)
+    }
+}
+
+proptest! {
+    #[test]
+    fn frames_survive_any_chunking(
+        texts in prop::collection::vec("[a-zA-Z0-9 ]{0,100}", 1..40),
+        chunk_size in 1usize..600,
+        max_capacity in 0usize..2048
+    ) {
+        let messages: Vec<Message> = texts.iter().map(|t| Message::chat("#general", "Jeb", t)).collect();
+
+        let mut data: Vec<u8> = Vec::new();
+        for message in &messages {
+            data.extend(frame(message));
+        }
+
+        let mut reader = ChunkedReader { data, position: 0, chunk_size };
+        let mut buffer = NetworkBuffer::with_limits(0, max_capacity);
+        let mut decoded: Vec<Message> = Vec::new();
+
+        loop {
+            match buffer.read_from(&mut reader) {
+                Ok(0) => break,
+                Ok(_) | Err(Error::BufferFull) => (),
+                Err(e) => panic!("unexpected error {}", e)
+            }
+
+            loop {
+                match deserialize_packet::<Message>(&mut buffer) {
+                    Ok(message) => decoded.push(message),
+                    Err(DecodeError::Incomplete) => break,
+                    Err(e) => panic!("unexpected error {}", e)
+                }
+            }
+        }
+
+        prop_assert!(buffer.is_empty());
+        prop_assert_eq!(decoded, messages);
+    }
+}
@@ -0,0 +1,22 @@
+use std::time::{Duration, Instant};
+use doosknet::*;
+
+// Polls both ends until `done` is satisfied with the events they produced
+pub fn pump<F: FnMut(&[NetworkEvent], &[NetworkEvent]) -> bool>(server: &mut Server, clients: &mut [&mut Client], mut done: F) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+
+    while Instant::now() < deadline {
+        let server_events = server.poll(Some(Duration::from_millis(5))).unwrap();
+
+        let mut client_events: Vec<NetworkEvent> = Vec::new();
+        for client in clients.iter_mut() {
+            client_events.extend(client.poll(Some(Duration::from_millis(5))).unwrap());
+        }
+
+        if done(&server_events, &client_events) {
+            return;
+        }
+    }
+
+    panic!("timed out");
+}
@@ -0,0 +1,165 @@
+extern crate doosknet;
+
+mod common;
+
+use std::thread;
+use std::time::{Duration, Instant};
+use doosknet::*;
+use common::pump;
+
+#[test]
+fn client_and_server_exchange_messages() {
+    let mut server = Server::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let mut client = Client::connect(&addr).unwrap();
+    client.send(&Message::hello("Jeb")).unwrap();
+
+    let mut conn: Option<ConnId> = None;
+    let mut hello: Option<Message> = None;
+    pump(&mut server, &mut [&mut client], |server_events, _| {
+        for event in server_events {
+            match *event {
+                NetworkEvent::Connected(id) => conn = Some(id),
+                NetworkEvent::Message(id, ref packet, _) => {
+                    assert_eq!(Some(id), conn);
+                    hello = Some(packet.clone());
+                },
+                _ => ()
+            }
+        }
+
+        hello.is_some()
+    });
+
+    assert_eq!(hello.unwrap(), Message::hello("Jeb"));
+    assert!(client.is_connected());
+
+    server.broadcast(&Message::notice("hi everyone")).unwrap();
+
+    let mut received: Vec<Message> = Vec::new();
+    pump(&mut server, &mut [&mut client], |_, client_events| {
+        for event in client_events {
+            if let NetworkEvent::Message(SERVER_CONN, ref packet, _) = *event {
+                received.push(packet.clone());
+            }
+        }
+
+        !received.is_empty()
+    });
+
+    assert_eq!(received, vec![Message::notice("hi everyone")]);
+}
+
+#[test]
+fn closed_connections_flush_before_disconnecting() {
+    let mut server = Server::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let mut client = Client::connect(&addr).unwrap();
+
+    let mut conn: Option<ConnId> = None;
+    pump(&mut server, &mut [&mut client], |server_events, _| {
+        for event in server_events {
+            if let NetworkEvent::Connected(id) = *event {
+                conn = Some(id);
+            }
+        }
+
+        conn.is_some()
+    });
+
+    let conn = conn.unwrap();
+    server.send(conn, &Message::rejected("go away")).unwrap();
+    server.close(conn);
+
+    let mut received: Vec<Message> = Vec::new();
+    let mut server_saw_disconnect = false;
+    pump(&mut server, &mut [&mut client], |server_events, client_events| {
+        for event in server_events {
+            if let NetworkEvent::Disconnected(id) = *event {
+                assert_eq!(id, conn);
+                server_saw_disconnect = true;
+            }
+        }
+
+        for event in client_events {
+            if let NetworkEvent::Message(_, ref packet, _) = *event {
+                received.push(packet.clone());
+            }
+        }
+
+        server_saw_disconnect && !received.is_empty()
+    });
+
+    assert_eq!(received, vec![Message::rejected("go away")]);
+    assert_eq!(server.connection_count(), 0);
+}
+
+#[test]
+fn stopped_servers_keep_their_connections() {
+    let mut server = Server::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let mut client = Client::connect(&addr).unwrap();
+    pump(&mut server, &mut [&mut client], |server_events, _| {
+        server_events.iter().any(|event| matches!(*event, NetworkEvent::Connected(_)))
+    });
+
+    server.stop_listening().unwrap();
+    assert!(server.local_addr().is_err());
+
+    // Nobody new gets in
+    let mut late_client = Client::connect(&addr).unwrap();
+    let mut refused = false;
+    for _ in 0..100 {
+        match late_client.poll(Some(Duration::from_millis(5))) {
+            Ok(_) if late_client.is_connected() => panic!("connected after the server stopped listening"),
+            Ok(_) => (),
+            Err(_) => {
+                refused = true;
+                break;
+            }
+        }
+    }
+
+    assert!(refused);
+
+    // But the existing connection still works
+    server.broadcast(&Message::notice("still here")).unwrap();
+
+    let mut received: Vec<Message> = Vec::new();
+    pump(&mut server, &mut [&mut client], |_, client_events| {
+        for event in client_events {
+            if let NetworkEvent::Message(_, ref packet, _) = *event {
+                received.push(packet.clone());
+            }
+        }
+
+        !received.is_empty()
+    });
+
+    assert_eq!(received, vec![Message::notice("still here")]);
+}
+
+#[test]
+fn wakers_interrupt_a_waiting_poll() {
+    let mut server = Server::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let waker = server.waker().unwrap();
+
+    // Twice, to check the waker can be used again once it has fired
+    for _ in 0..2 {
+        let other = waker.clone();
+        let wake = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            other.wake().unwrap();
+        });
+
+        let started = Instant::now();
+        let events: Vec<NetworkEvent> = server.poll(Some(Duration::from_secs(10))).unwrap();
+        wake.join().unwrap();
+
+        assert!(events.is_empty());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
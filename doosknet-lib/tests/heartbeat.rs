@@ -0,0 +1,107 @@
+extern crate doosknet;
+
+mod common;
+
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+use doosknet::*;
+use common::pump;
+
+fn heartbeat() -> Heartbeat {
+    Heartbeat {
+        interval: Duration::from_millis(50),
+        timeout: Duration::from_millis(200)
+    }
+}
+
+#[test]
+fn answered_pings_keep_quiet_connections_alive() {
+    let mut server = Server::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    server.set_heartbeat(Some(heartbeat()));
+    let addr = server.local_addr().unwrap();
+
+    // The client answers pings even without a heartbeat of its own
+    let mut client = Client::connect(&addr).unwrap();
+
+    let mut events: Vec<NetworkEvent> = Vec::new();
+    let until = Instant::now() + Duration::from_millis(600);
+    while Instant::now() < until {
+        events.extend(server.poll(Some(Duration::from_millis(5))).unwrap());
+        events.extend(client.poll(Some(Duration::from_millis(5))).unwrap());
+    }
+
+    assert!(client.is_connected());
+    assert_eq!(server.connection_count(), 1);
+
+    for event in events {
+        match event {
+            NetworkEvent::Connected(_) => (),
+            other => panic!("unexpected event {:?}", other)
+        }
+    }
+}
+
+#[test]
+fn server_drops_peers_that_stop_answering() {
+    let mut server = Server::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    server.set_heartbeat(Some(heartbeat()));
+    let addr = server.local_addr().unwrap();
+
+    // Never reads, so never answers a ping
+    let _socket = TcpStream::connect(addr).unwrap();
+
+    let mut conn: Option<ConnId> = None;
+    let mut timed_out: Option<ConnId> = None;
+    let mut disconnected: Option<ConnId> = None;
+    pump(&mut server, &mut [], |server_events, _| {
+        for event in server_events {
+            match *event {
+                NetworkEvent::Connected(id) => conn = Some(id),
+                NetworkEvent::TimedOut(id) => {
+                    assert!(disconnected.is_none(), "TimedOut must come before Disconnected");
+                    timed_out = Some(id);
+                },
+                NetworkEvent::Disconnected(id) => disconnected = Some(id),
+                _ => ()
+            }
+        }
+
+        disconnected.is_some()
+    });
+
+    assert!(conn.is_some());
+    assert_eq!(timed_out, conn);
+    assert_eq!(disconnected, conn);
+    assert_eq!(server.connection_count(), 0);
+}
+
+#[test]
+fn client_gives_up_on_a_silent_server() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut client = Client::connect(&addr).unwrap();
+    client.set_heartbeat(Some(heartbeat()));
+
+    // Accepts, then never says anything back
+    let (_socket, _) = listener.accept().unwrap();
+
+    let mut events: Vec<NetworkEvent> = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !client.is_disconnected() && Instant::now() < deadline {
+        events.extend(client.poll(Some(Duration::from_millis(5))).unwrap());
+    }
+
+    assert!(client.is_disconnected());
+
+    let kinds: Vec<&str> = events.iter()
+        .map(|event| match *event {
+            NetworkEvent::Connected(_) => "connected",
+            NetworkEvent::TimedOut(_) => "timed out",
+            NetworkEvent::Disconnected(_) => "disconnected",
+            _ => "other"
+        })
+        .collect();
+
+    assert_eq!(kinds, vec!["connected", "timed out", "disconnected"]);
+}
@@ -0,0 +1,109 @@
+extern crate doosknet;
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::SocketAddr;
+use std::time::Duration;
+use doosknet::*;
+
+fn poll_server(server: &mut Server) -> Vec<NetworkEvent> {
+    server.poll(Some(Duration::from_secs(0))).unwrap()
+}
+
+fn poll_client(client: &mut Client) -> Vec<NetworkEvent> {
+    client.poll(Some(Duration::from_secs(0))).unwrap()
+}
+
+fn messages(events: &[NetworkEvent]) -> Vec<Message> {
+    events.iter()
+        .filter_map(|event| match *event {
+            NetworkEvent::Message(_, ref packet, _) => Some(packet.clone()),
+            _ => None
+        })
+        .collect()
+}
+
+fn peer() -> SocketAddr {
+    "10.0.0.1:5000".parse().unwrap()
+}
+
+#[test]
+fn pipes_carry_bytes_both_ways_until_closed() {
+    let (mut a, mut b) = pipe();
+    let mut buf = [0u8; 8];
+
+    assert_eq!(b.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+    a.write_all(b"hello").unwrap();
+    b.write_all(b"hi").unwrap();
+    assert_eq!(b.read(&mut buf).unwrap(), 5);
+    assert_eq!(&buf[..5], b"hello");
+    assert_eq!(a.read(&mut buf).unwrap(), 2);
+    assert_eq!(&buf[..2], b"hi");
+
+    // What was written before closing is still delivered
+    a.write_all(b"bye").unwrap();
+    drop(a);
+    assert_eq!(b.read(&mut buf).unwrap(), 3);
+    assert_eq!(b.read(&mut buf).unwrap(), 0);
+    assert_eq!(b.write(b"anyone?").unwrap_err().kind(), ErrorKind::BrokenPipe);
+}
+
+#[test]
+fn pipe_connections_need_no_listener() {
+    let mut server = Server::unbound().unwrap();
+    assert!(server.local_addr().is_err());
+
+    let mut client = server.connect_pipe(&peer()).unwrap();
+    client.send(&Message::hello("Jeb")).unwrap();
+
+    let client_events: Vec<NetworkEvent> = poll_client(&mut client);
+    assert!(matches!(client_events[0], NetworkEvent::Connected(SERVER_CONN)));
+
+    let server_events: Vec<NetworkEvent> = poll_server(&mut server);
+    let conn: ConnId = match server_events[0] {
+        NetworkEvent::Connected(id) => id,
+        ref event => panic!("expected a connection, got {:?}", event)
+    };
+    assert_eq!(messages(&server_events), vec![Message::hello("Jeb")]);
+    assert!(matches!(server_events[1], NetworkEvent::Message(_, _, bytes) if bytes == serialize_packet(&Message::hello("Jeb")).unwrap().len()));
+    assert_eq!(server.peer_addr(conn), Some(peer()));
+
+    server.send(conn, &Message::notice("one")).unwrap();
+    server.send(conn, &Message::notice("two")).unwrap();
+    poll_server(&mut server);
+
+    assert_eq!(messages(&poll_client(&mut client)), vec![Message::notice("one"), Message::notice("two")]);
+}
+
+#[test]
+fn pipe_connections_report_disconnects() {
+    let mut server = Server::unbound().unwrap();
+    let mut kept = server.connect_pipe(&peer()).unwrap();
+    let dropped = server.connect_pipe(&peer()).unwrap();
+    poll_client(&mut kept);
+
+    let ids: Vec<ConnId> = poll_server(&mut server).iter()
+        .filter_map(|event| match *event {
+            NetworkEvent::Connected(id) => Some(id),
+            _ => None
+        })
+        .collect();
+    assert_eq!(ids.len(), 2);
+
+    // A client going away
+    drop(dropped);
+    let events: Vec<NetworkEvent> = poll_server(&mut server);
+    assert!(matches!(events[..], [NetworkEvent::Disconnected(id)] if id == ids[1]));
+
+    // The server closing a connection, after what it already queued
+    server.send(ids[0], &Message::rejected("go away")).unwrap();
+    server.close(ids[0]);
+    let events: Vec<NetworkEvent> = poll_server(&mut server);
+    assert!(matches!(events[..], [NetworkEvent::Disconnected(id)] if id == ids[0]));
+    assert_eq!(server.connection_count(), 0);
+
+    let events: Vec<NetworkEvent> = poll_client(&mut kept);
+    assert_eq!(messages(&events), vec![Message::rejected("go away")]);
+    assert!(matches!(events.last(), Some(&NetworkEvent::Disconnected(SERVER_CONN))));
+    assert!(kept.is_disconnected());
+}
@@ -0,0 +1,77 @@
+extern crate doosknet;
+
+use std::io::{self, Write};
+use doosknet::*;
+
+// Writer that accepts a few bytes per call and then blocks until it's unblocked again
+struct StingyWriter {
+    data: Vec<u8>,
+    chunk_size: usize,
+    budget: usize
+}
+
+impl Write for StingyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.budget == 0 {
+            return Err(io::Error::new(io::ErrorKind::WouldBlock, "would block"));
+        }
+
+        let count = self.chunk_size.min(buf.len()).min(self.budget);
+        self.data.extend_from_slice(&buf[..count]);
+        self.budget -= count;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn partial_writes_resume_where_they_left_off() {
+    let messages: Vec<Message> = (0..5).map(|i| Message::chat("#general", "Bill", &format!("hello {}", i))).collect();
+
+    let mut queue = SendQueue::new();
+    let mut expected: Vec<u8> = Vec::new();
+    for message in &messages {
+        queue.push(message).unwrap();
+        expected.extend(serialize_packet(message).unwrap());
+    }
+
+    assert_eq!(queue.queued_bytes(), expected.len());
+    assert_eq!(queue.queued_frames(), messages.len());
+
+    let mut writer = StingyWriter { data: Vec::new(), chunk_size: 7, budget: 0 };
+    while !queue.is_empty() {
+        // Every flush runs into WouldBlock part way through a frame
+        let queued_bytes = queue.queued_bytes();
+        writer.budget = 10;
+        assert_eq!(queue.flush(&mut writer).unwrap(), queued_bytes.min(10));
+        assert_eq!(queue.queued_bytes(), expected.len() - writer.data.len());
+    }
+
+    assert_eq!(writer.data, expected);
+    assert_eq!(queue.sent_bytes(), expected.len() as u64);
+    assert_eq!(queue.sent_frames(), messages.len() as u64);
+
+    let mut buffer = NetworkBuffer::new();
+    buffer.extend_from_slice(&writer.data);
+    for message in &messages {
+        assert_eq!(&deserialize_packet::<Message>(&mut buffer).unwrap(), message);
+    }
+}
+
+#[test]
+fn would_block_keeps_the_packet() {
+    let mut queue = SendQueue::new();
+    queue.push(&Message::notice("still here")).unwrap();
+
+    let mut writer = StingyWriter { data: Vec::new(), chunk_size: 64, budget: 0 };
+    assert_eq!(queue.flush(&mut writer).unwrap(), 0);
+    assert_eq!(queue.queued_frames(), 1);
+
+    writer.budget = 1024;
+    queue.flush(&mut writer).unwrap();
+    assert!(queue.is_empty());
+    assert_eq!(writer.data, serialize_packet(&Message::notice("still here")).unwrap());
+}
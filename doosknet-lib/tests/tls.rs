@@ -0,0 +1,171 @@
+extern crate doosknet;
+extern crate rcgen;
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use doosknet::*;
+use doosknet::tls::{self, ServerConfig};
+use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+use common::pump;
+
+struct TestCerts {
+    dir: PathBuf,
+    ca: PathBuf,
+    cert: PathBuf,
+    key: PathBuf
+}
+
+impl Drop for TestCerts {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// A CA, and a certificate for localhost signed by it
+fn generate_certs(name: &str) -> TestCerts {
+    let dir = env::temp_dir().join(format!("doosknet-tls-{}-{}", process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+
+    let key = KeyPair::generate().unwrap();
+    let cert = CertificateParams::new(vec![String::from("localhost")]).unwrap()
+        .signed_by(&key, &ca_cert, &ca_key)
+        .unwrap();
+
+    let certs = TestCerts {
+        ca: dir.join("ca.pem"),
+        cert: dir.join("cert.pem"),
+        key: dir.join("key.pem"),
+        dir
+    };
+
+    fs::write(&certs.ca, ca_cert.pem()).unwrap();
+    fs::write(&certs.cert, cert.pem()).unwrap();
+    fs::write(&certs.key, key.serialize_pem()).unwrap();
+
+    certs
+}
+
+// Runs a Hello from the client and a Notice from the server over the given connection
+fn exchange(server: &mut Server, client: &mut Client) {
+    client.send(&Message::hello("Mitch")).unwrap();
+
+    let mut conn: Option<ConnId> = None;
+    pump(server, &mut [&mut *client], |server_events, _| {
+        for event in server_events {
+            if let NetworkEvent::Message(id, ref packet, _) = *event {
+                assert_eq!(*packet, Message::hello("Mitch"));
+                conn = Some(id);
+            }
+        }
+
+        conn.is_some()
+    });
+
+    server.send(conn.unwrap(), &Message::notice("secret")).unwrap();
+
+    let mut received: Option<Message> = None;
+    pump(server, &mut [&mut *client], |_, client_events| {
+        for event in client_events {
+            if let NetworkEvent::Message(_, ref packet, _) = *event {
+                received = Some(packet.clone());
+            }
+        }
+
+        received.is_some()
+    });
+
+    assert_eq!(received.unwrap(), Message::notice("secret"));
+}
+
+#[test]
+fn client_verifies_server_against_ca() {
+    let certs = generate_certs("ca");
+
+    let server_config = tls::load_server_config(&certs.cert, &certs.key).unwrap();
+    let mut server = Server::bind_tls(&"127.0.0.1:0".parse().unwrap(), server_config).unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let client_config = tls::load_client_config(&certs.ca).unwrap();
+    let mut client = Client::connect_tls(&addr, "localhost", client_config).unwrap();
+
+    exchange(&mut server, &mut client);
+}
+
+#[test]
+fn client_accepts_pinned_fingerprint() {
+    let certs = generate_certs("pinned");
+
+    let server_config = tls::load_server_config(&certs.cert, &certs.key).unwrap();
+    let mut server = Server::bind_tls(&"127.0.0.1:0".parse().unwrap(), server_config).unwrap();
+    let addr = server.local_addr().unwrap();
+
+    // Pinning skips name checks, so any name will do
+    let fingerprint = tls::certificate_fingerprint(&certs.cert).unwrap();
+    let client_config = tls::pinned_client_config(&fingerprint).unwrap();
+    let mut client = Client::connect_tls(&addr, "rustychat.invalid", client_config).unwrap();
+
+    exchange(&mut server, &mut client);
+}
+
+#[test]
+fn client_rejects_wrong_fingerprint() {
+    let certs = generate_certs("wrong");
+
+    let server_config = tls::load_server_config(&certs.cert, &certs.key).unwrap();
+    let mut server = Server::bind_tls(&"127.0.0.1:0".parse().unwrap(), server_config).unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let client_config = tls::pinned_client_config(&"00".repeat(32)).unwrap();
+    let mut client = Client::connect_tls(&addr, "localhost", client_config).unwrap();
+    client.send(&Message::hello("Deboe")).unwrap();
+
+    let mut server_messages: usize = 0;
+    pump(&mut server, &mut [&mut client], |server_events, client_events| {
+        server_messages += server_events.iter()
+            .filter(|e| matches!(**e, NetworkEvent::Message(..)))
+            .count();
+
+        client_events.iter().any(|e| matches!(*e, NetworkEvent::Disconnected(_)))
+    });
+
+    assert_eq!(server_messages, 0);
+}
+
+#[test]
+fn failed_tls_setup_drops_only_that_connection() {
+    let certs = generate_certs("setup");
+
+    // Too small a fragment size for rustls, so every session fails to start
+    let mut config: ServerConfig = (*tls::load_server_config(&certs.cert, &certs.key).unwrap()).clone();
+    config.max_fragment_size = Some(10);
+    let mut server = Server::bind_tls(&"127.0.0.1:0".parse().unwrap(), Arc::new(config)).unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let mut clients: Vec<Client> = (0..2).map(|_| Client::connect(&addr).unwrap()).collect();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while clients.iter().any(|client| !client.is_disconnected()) && Instant::now() < deadline {
+        assert!(server.poll(Some(Duration::from_millis(5))).unwrap().is_empty());
+        for client in &mut clients {
+            client.poll(Some(Duration::from_millis(5))).unwrap();
+        }
+    }
+
+    assert!(clients.iter().all(Client::is_disconnected));
+    assert_eq!(server.connection_count(), 0);
+}
+
+#[test]
+fn invalid_fingerprint_is_an_error() {
+    assert!(tls::pinned_client_config("not a fingerprint").is_err());
+}
@@ -0,0 +1,41 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+// Delays between reconnection attempts: doubling from `base` up to `max`, each shortened by a random amount
+// so a crowd of clients dropped by the same server restart doesn't come back all at once
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    attempts: u32
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Backoff {
+            base,
+            max,
+            attempts: 0
+        }
+    }
+
+    // How long to wait before the next attempt. Between half and all of the full delay.
+    pub fn next_delay(&mut self) -> Duration {
+        let full: Duration = self.base.checked_mul(1 << self.attempts.min(16)).unwrap_or(self.max).min(self.max);
+        self.attempts += 1;
+
+        full / 2 + full.mul_f64(random_fraction() / 2.0)
+    }
+
+    // Called once connected again, so the next outage starts from `base`
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+// A number in [0, 1). Every RandomState is seeded differently, which is plenty for jitter.
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,608 @@
+use std::collections::HashMap;
+use std::mem;
+use std::thread;
+use std::time::{Duration, Instant};
+use backoff::Backoff;
+use doosknet::*;
+use connector::Connector;
+
+// What happened during an update, for front-ends and bots that want more than the message log
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChatEvent {
+    // A connection was made. Nothing can be sent until we are welcomed.
+    Connected,
+    // The server accepted us as `username`
+    Welcomed { username: String },
+    // The connection was lost, or could not be made. `retry_in` is when we try again,
+    // or None if the server turned us away and we won't.
+    Disconnected { reason: String, retry_in: Option<Duration> },
+    // Everything the server sends, once the client has dealt with it
+    Received(Message)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConnectionState {
+    // Not connected. We try again at the given time, or never once the server has turned us away.
+    Offline(Option<Instant>),
+    // Connected, and waiting to be welcomed
+    Connecting,
+    Online
+}
+
+// Everything a client knows about its connection and its chat, whatever it is drawn with or driven by.
+// Feed it typed lines and keys, call `update` often, and show `messages` and `prompt`.
+pub struct ChatClient<C: Connector> {
+    connector: C,
+
+    // The connection to the server, if we have one. When it drops, we keep trying to get it back.
+    transport: Option<C::Transport>,
+    reconnect_at: Option<Instant>,
+    backoff: Backoff,
+
+    // Set once the server has turned us away; reconnecting would only be turned away again
+    is_rejected: bool,
+
+    // Lines to show, oldest first
+    pub messages: Vec<String>,
+
+    // What has happened since the last update
+    events: Vec<ChatEvent>,
+
+    // The nickname the server accepted on this connection. Until then, typed lines are nickname attempts.
+    username: Option<String>,
+
+    // The nickname to introduce ourselves with, now and after reconnecting
+    nickname: Option<String>,
+
+    // Set when the server turns our nickname down, e.g. because the connection we lost still holds it.
+    // Typed lines are nickname attempts again until we are welcomed.
+    is_choosing_nickname: bool,
+
+    // Once we have been welcomed, lines typed while offline wait here until we are back
+    was_welcomed: bool,
+    outbox: Vec<Message>,
+
+    // Channels we are in, and the one typed lines go to. Tab switches between them.
+    channels: Vec<String>,
+    channel: String,
+
+    // Oldest history entry we have seen in each channel, so "/history" and PageUp can fetch the ones before it
+    oldest_seen: HashMap<String, u64>,
+
+    // Who "/r <message>" replies to: the last person we exchanged a direct message with
+    last_direct: Option<String>,
+
+    // Password for a registered nickname, given up front or typed when the server asks for it.
+    // Kept so we can log in again after reconnecting.
+    password: Option<String>,
+    password_for: Option<String>
+}
+
+impl<C: Connector> ChatClient<C> {
+    // Connects on the first `update`. Without a nickname, the first line submitted is used.
+    pub fn new(connector: C, nickname: Option<String>, password: Option<String>) -> ChatClient<C> {
+        let mut messages: Vec<String> = Vec::new();
+
+        // Introduce ourselves before any chat traffic, with the nickname we were given or the first line typed
+        if nickname.is_none() {
+            messages.push(String::from("Choose a nickname and press Enter:"));
+        }
+
+        ChatClient {
+            connector,
+            transport: None,
+            reconnect_at: Some(Instant::now()),
+            backoff: Backoff::new(Duration::from_secs(1), Duration::from_secs(30)),
+            is_rejected: false,
+            messages,
+            events: Vec::new(),
+            username: None,
+            nickname,
+            is_choosing_nickname: false,
+            was_welcomed: false,
+            outbox: Vec::new(),
+            channels: Vec::new(),
+            channel: String::new(),
+            oldest_seen: HashMap::new(),
+            last_direct: None,
+            password,
+            password_for: None
+        }
+    }
+
+    // Reconnection attempts wait from `base` doubling up to `max`, a second and 30 seconds unless set
+    pub fn set_reconnect_delays(&mut self, base: Duration, max: Duration) {
+        self.backoff = Backoff::new(base, max);
+    }
+
+    pub fn connector(&self) -> &C {
+        &self.connector
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        match self.transport {
+            Some(_) if self.username.is_some() => ConnectionState::Online,
+            Some(_) => ConnectionState::Connecting,
+            None => ConnectionState::Offline(self.reconnect_at)
+        }
+    }
+
+    // The nickname we are logged in with, if we are
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    // The channel typed lines go to
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    // Packets waiting for us to be back online, oldest first
+    pub fn queued(&self) -> &[Message] {
+        &self.outbox
+    }
+
+    // A line typed and sent with Enter: chat, a command, or while logging in, a nickname or password
+    pub fn submit(&mut self, line: &str) {
+        if line.is_empty() {
+            return;
+        }
+
+        let message: String = String::from(line);
+        match self.username {
+            Some(ref username) => {
+                let packet: Result<Message, String> = if message == "/history" {
+                    history_request(&self.channel, &self.oldest_seen, &mut self.messages)
+                }
+                else {
+                    direct_message(username, &message, &self.last_direct)
+                        .map(|packet| packet.unwrap_or_else(|| Message::chat(&self.channel, username, &message)))
+                };
+
+                match packet {
+                    Ok(packet) => {
+                        send_packet(&mut self.transport, &packet, &mut self.messages);
+                    },
+                    Err(reason) => self.messages.push(format!("Error: {}", reason))
+                }
+            },
+            None if self.was_welcomed && self.password_for.is_none() && !self.is_choosing_nickname => {
+                let sender: String = self.nickname.clone().unwrap_or_default();
+                let packet: Result<Message, String> = if message == "/history" {
+                    Err(String::from("Not connected."))
+                }
+                else {
+                    direct_message(&sender, &message, &self.last_direct)
+                        .map(|packet| packet.unwrap_or_else(|| Message::chat(&self.channel, &sender, &message)))
+                };
+
+                match packet {
+                    Ok(packet) => {
+                        self.outbox.push(packet);
+                        self.messages.push(format!("(queued) {}", mask_password(&message, false)));
+                    },
+                    Err(reason) => self.messages.push(format!("Error: {}", reason))
+                }
+            },
+            None => {
+                let is_new_nickname: bool = match (self.password_for.take(), message.strip_prefix("/nick ")) {
+                    (Some(_), Some(new_nickname)) => {
+                        self.nickname = Some(String::from(new_nickname.trim()));
+                        true
+                    },
+                    (Some(registered), None) => {
+                        self.password = Some(message.clone());
+                        send_packet(&mut self.transport, &Message::login(&registered, &message), &mut self.messages);
+                        false
+                    },
+                    (None, _) => {
+                        self.nickname = Some(message.clone());
+                        true
+                    }
+                };
+
+                // A new nickname is tried right away, or as soon as we are connected
+                if is_new_nickname {
+                    if let Some(ref nickname) = self.nickname {
+                        if self.transport.is_none() {
+                            self.messages.push(format!("Not connected yet. Will use {} once connected.", nickname));
+                        }
+
+                        send_packet(&mut self.transport, &Message::hello(nickname), &mut self.messages);
+                    }
+                }
+            }
+        }
+    }
+
+    // Fetches the messages before the oldest one we have seen in the current channel. Bound to PageUp.
+    pub fn request_history(&mut self) {
+        if self.username.is_none() {
+            return;
+        }
+
+        match history_request(&self.channel, &self.oldest_seen, &mut self.messages) {
+            Ok(packet) => {
+                send_packet(&mut self.transport, &packet, &mut self.messages);
+            },
+            Err(reason) => self.messages.push(format!("Error: {}", reason))
+        }
+    }
+
+    // Types into the channel after the current one. Bound to Tab.
+    pub fn next_channel(&mut self) {
+        if self.channels.is_empty() {
+            return;
+        }
+
+        let next: usize = self.channels.iter().position(|name| *name == self.channel).map_or(0, |i| (i + 1) % self.channels.len());
+        self.channel = self.channels[next].clone();
+    }
+
+    // What has been typed so far, with any password hidden
+    pub fn mask_input(&self, input: &str) -> String {
+        mask_password(input, self.password_for.is_some())
+    }
+
+    // Sends `text` to `channel` for a bot, or queues it while we are offline once we have been welcomed
+    pub fn say(&mut self, channel: &str, text: &str) -> Result<(), String> {
+        match self.username {
+            Some(ref username) => {
+                send_packet(&mut self.transport, &Message::chat(channel, username, text), &mut self.messages);
+                Ok(())
+            },
+            None if self.was_welcomed => {
+                self.outbox.push(Message::chat(channel, &self.nickname.clone().unwrap_or_default(), text));
+                Ok(())
+            },
+            None => Err(String::from("Not logged in yet."))
+        }
+    }
+
+    // "[offline, retrying in 3s] " and the like while we are not fully connected, or nothing once we are
+    pub fn status(&self) -> String {
+        let queued: String = if !self.outbox.is_empty() {
+            format!(", {} queued", self.outbox.len())
+        }
+        else {
+            String::new()
+        };
+
+        match self.state() {
+            ConnectionState::Online => String::new(),
+            ConnectionState::Connecting => format!("[connecting{}] ", queued),
+            ConnectionState::Offline(Some(at)) => format!("[offline{}, retrying in {}s] ", queued, at.saturating_duration_since(Instant::now()).as_secs() + 1),
+            ConnectionState::Offline(None) => format!("[offline{}] ", queued)
+        }
+    }
+
+    // The input line: the status, the channel and what has been typed
+    pub fn prompt(&self, input: &str) -> String {
+        format!("{}{}> {}", self.status(), self.channel, self.mask_input(input))
+    }
+
+    // Connects or reconnects when it is time to, and handles whatever the server has sent.
+    // Waits up to `timeout` for something to arrive. Returns what happened.
+    pub fn update(&mut self, timeout: Duration) -> Vec<ChatEvent> {
+        if self.reconnect_at.is_some_and(|at| Instant::now() >= at) {
+            self.reconnect_at = None;
+
+            match self.connector.connect() {
+                Ok(transport) => {
+                    self.transport = Some(transport);
+                    if let Some(ref nickname) = self.nickname {
+                        send_packet(&mut self.transport, &Message::hello(nickname), &mut self.messages);
+                    }
+                },
+                Err(e) => {
+                    warn!("Failed to connect: {}", e);
+                    self.lose_connection(&format!("Couldn't connect to {}", e));
+                }
+            }
+        }
+
+        let mut lost: Option<String> = None;
+        let events: Vec<NetworkEvent> = match self.transport {
+            Some(ref mut transport) => match transport.poll(Some(timeout)) {
+                Ok(events) => events,
+                Err(e) => {
+                    warn!("Connection failed: {}", e);
+                    lost = Some(format!("Connection failed: {}", e));
+                    Vec::new()
+                }
+            },
+            None => {
+                thread::sleep(timeout);
+                Vec::new()
+            }
+        };
+
+        for event in events {
+            match event {
+                NetworkEvent::Connected(_) => {
+                    info!("Connected to the server");
+                    self.events.push(ChatEvent::Connected);
+                },
+                NetworkEvent::Disconnected(_) => {
+                    info!("Disconnected from the server");
+                    lost = Some(String::from("Disconnected from the server."));
+                },
+                NetworkEvent::TimedOut(_) => {
+                    warn!("The server stopped responding");
+                    self.messages.push(String::from("The server stopped responding."));
+                },
+                // Retrying won't help; the endpoint closes the connection after this
+                NetworkEvent::DecodeFailed(_, DecodeError::UnsupportedVersion(version)) => {
+                    warn!("The server speaks protocol version {}", version);
+                    self.messages.push(format!("Error: The server speaks protocol version {}, but this client speaks version {}. Update whichever is older.", version, PROTOCOL_VERSION));
+                    self.is_rejected = true;
+                },
+                NetworkEvent::DecodeFailed(_, e) => {
+                    warn!("Failed to decode a packet from the server: {}", e);
+                },
+                NetworkEvent::Message(_, packet, _) => {
+                    trace!("Received {:?}", packet);
+                    self.handle_message(packet);
+                }
+            }
+        }
+
+        if let Some(reason) = lost {
+            self.transport = None;
+            self.username = None;
+            self.password_for = None;
+            self.lose_connection(&reason);
+        }
+
+        mem::take(&mut self.events)
+    }
+
+    // Says why we are offline, and when we will try again
+    fn lose_connection(&mut self, reason: &str) {
+        if self.is_rejected {
+            self.messages.push(format!("{} Not reconnecting. Restart the client to try again.", reason));
+            self.events.push(ChatEvent::Disconnected { reason: String::from(reason), retry_in: None });
+            return;
+        }
+
+        let delay: Duration = self.backoff.next_delay();
+        self.messages.push(format!("{} Reconnecting in {:.1} seconds...", reason, delay.as_secs_f64()));
+        self.events.push(ChatEvent::Disconnected { reason: String::from(reason), retry_in: Some(delay) });
+        self.reconnect_at = Some(Instant::now() + delay);
+    }
+
+    fn handle_message(&mut self, packet: Message) {
+        match packet.clone() {
+            Message::Welcome { username: accepted, server_name, motd, .. } => {
+                self.messages.push(format!("Connected to {} as {}.", server_name, accepted));
+                if !self.was_welcomed {
+                    self.messages.push(motd);
+                }
+
+                self.username = Some(accepted.clone());
+                self.nickname = Some(accepted.clone());
+                self.is_choosing_nickname = false;
+                self.backoff.reset();
+                self.events.push(ChatEvent::Welcomed { username: accepted.clone() });
+
+                // Back after a disconnect: rejoin our channels, ending in the one we were typing in,
+                // then send whatever was typed in the meantime
+                if self.was_welcomed {
+                    let mut rejoin: Vec<String> = self.channels.iter().filter(|name| **name != self.channel).cloned().collect();
+                    if !self.channel.is_empty() {
+                        rejoin.push(self.channel.clone());
+                    }
+
+                    for name in rejoin {
+                        send_packet(&mut self.transport, &Message::chat(&name, &accepted, &format!("/join {}", name)), &mut self.messages);
+                    }
+
+                    if !self.outbox.is_empty() {
+                        self.messages.push(format!("Sending {} queued message{}.", self.outbox.len(), if self.outbox.len() == 1 { "" } else { "s" }));
+                    }
+
+                    for packet in self.outbox.drain(..) {
+                        send_packet(&mut self.transport, &packet, &mut self.messages);
+                    }
+                }
+
+                self.was_welcomed = true;
+            },
+            Message::Rejected { reason } => {
+                warn!("The server rejected the connection: {}", reason);
+                self.messages.push(format!("Error: {}", reason));
+                self.is_rejected = true;
+            },
+            Message::AuthRequired { username: nickname, reason } => {
+                if !reason.is_empty() {
+                    self.messages.push(format!("Error: {}", reason));
+                }
+
+                // Try the password we have (from the command line, or typed before a reconnect), then ask
+                match self.password {
+                    Some(ref password) if reason.is_empty() => {
+                        send_packet(&mut self.transport, &Message::login(&nickname, password), &mut self.messages);
+                    },
+                    _ => {
+                        self.messages.push(format!("{} is registered. Enter its password, or /nick <nickname> to use another nickname:", nickname));
+                        self.password = None;
+                        self.password_for = Some(nickname);
+                    }
+                }
+            },
+            Message::Login { .. } => (),
+            Message::NicknameRejected { reason, .. } => {
+                self.messages.push(format!("Error: {}", reason));
+                self.messages.push(String::from("Choose another nickname:"));
+                self.is_choosing_nickname = true;
+            },
+            Message::Hello { .. } => (),
+            Message::Chat { channel: from, sender, message } => {
+                self.messages.push(format!("{}{} says \"{}\"", channel_prefix(&from, &self.channel), sender, message));
+            },
+            Message::DirectMessage { sender, target, message } => {
+                // We see our own messages echoed back by the server
+                if self.username.as_ref() == Some(&sender) {
+                    self.messages.push(format!("[DM to {}] {}", target, message));
+                    self.last_direct = Some(target);
+                }
+                else {
+                    self.messages.push(format!("[DM from {}] {}", sender, message));
+                    self.last_direct = Some(sender);
+                }
+            },
+            Message::Join { channel: joined, username: who } => {
+                self.messages.push(format!("* {} joined {}.", who, joined));
+            },
+            Message::Leave { channel: left, username: who } => {
+                self.messages.push(format!("* {} left {}.", who, left));
+
+                // When we leave, carry on in one of our other channels
+                if self.username.as_ref() == Some(&who) {
+                    self.channels.retain(|name| *name != left);
+                    if self.channel == left {
+                        self.channel = self.channels.last().cloned().unwrap_or_default();
+                    }
+                }
+            },
+            Message::Joined { channel: joined, topic } => {
+                if !topic.is_empty() {
+                    self.messages.push(format!("The topic of {} is: {}", joined, topic));
+                }
+
+                if !self.channels.contains(&joined) {
+                    self.channels.push(joined.clone());
+                }
+
+                self.channel = joined;
+            },
+            Message::HistoryEntry { channel: from, id, timestamp, sender, message, is_action } => {
+                let oldest: &mut u64 = self.oldest_seen.entry(from.clone()).or_insert(id);
+                *oldest = (*oldest).min(id);
+
+                let prefix: String = format!("{}[{}] ", channel_prefix(&from, &self.channel), format_time(timestamp));
+                if is_action {
+                    self.messages.push(format!("{}* {} {}", prefix, sender, message));
+                }
+                else {
+                    self.messages.push(format!("{}{} says \"{}\"", prefix, sender, message));
+                }
+            },
+            Message::HistoryEnd { channel: from, has_more } => {
+                if !has_more {
+                    self.messages.push(format!("--- Start of {} ---", from));
+                    self.oldest_seen.insert(from, 1);
+                }
+            },
+            Message::HistoryRequest { .. } => (),
+            Message::Topic { channel: changed, username: who, topic } => {
+                self.messages.push(format!("* {} changed the topic of {} to: {}", who, changed, topic));
+            },
+            Message::Action { channel: from, sender, message } => {
+                self.messages.push(format!("{}* {} {}", channel_prefix(&from, &self.channel), sender, message));
+            },
+            Message::NickChange { old_username, new_username } => {
+                self.messages.push(format!("* {} is now known as {}.", old_username, new_username));
+
+                if self.username.as_ref() == Some(&old_username) {
+                    self.username = Some(new_username.clone());
+                    self.nickname = Some(new_username);
+                }
+            },
+            Message::Notice { message } => self.messages.push(format!("[{}]", message)),
+            Message::Error { message } => self.messages.push(format!("Error: {}", message)),
+            Message::CommandReply { message } => self.messages.push(message),
+            Message::Ping(_) | Message::Pong(_) => ()
+        }
+
+        self.events.push(ChatEvent::Received(packet));
+    }
+}
+
+// Hides passwords as they are typed, whether at the login prompt or in /register, /login and /passwd
+fn mask_password(input: &str, is_password: bool) -> String {
+    let visible: usize = if is_password {
+        0
+    }
+    else if input.starts_with("/register ") || input.starts_with("/passwd ") {
+        input.find(' ').map_or(input.len(), |i| i + 1)
+    }
+    else if let Some(rest) = input.strip_prefix("/login ") {
+        // The nickname stays visible
+        rest.find(' ').map_or(input.len(), |i| "/login ".len() + i + 1)
+    }
+    else {
+        input.len()
+    };
+
+    format!("{}{}", &input[..visible], "*".repeat(input[visible..].chars().count()))
+}
+
+// Asks for the messages before the oldest one we have seen in `channel`
+fn history_request(channel: &str, oldest_seen: &HashMap<String, u64>, messages: &mut Vec<String>) -> Result<Message, String> {
+    if channel.is_empty() {
+        return Err(String::from("You are not in a channel."));
+    }
+
+    let before: u64 = oldest_seen.get(channel).cloned().unwrap_or(0);
+    if before == 1 {
+        return Err(format!("There is nothing older in {}.", channel));
+    }
+
+    messages.push(format!("--- Earlier in {} ---", channel));
+
+    Ok(Message::HistoryRequest { channel: String::from(channel), before, count: 20 })
+}
+
+// "HH:MM" in UTC
+fn format_time(timestamp: u64) -> String {
+    let minutes: u64 = timestamp / 60;
+    format!("{:02}:{:02}", (minutes / 60) % 24, minutes % 60)
+}
+
+// Turns "/msg <nickname> <message>" and "/r <message>" into a direct message packet.
+// Returns None for any other line.
+fn direct_message(username: &str, line: &str, last_direct: &Option<String>) -> Result<Option<Message>, String> {
+    if let Some(rest) = line.strip_prefix("/msg ") {
+        return match rest.trim_start().split_once(' ') {
+            Some((target, message)) if !message.trim().is_empty() => Ok(Some(Message::direct_message(username, target, message.trim()))),
+            _ => Err(String::from("Usage: /msg <nickname> <message>"))
+        };
+    }
+
+    if let Some(message) = line.strip_prefix("/r ") {
+        return match *last_direct {
+            Some(ref target) => Ok(Some(Message::direct_message(username, target, message.trim()))),
+            None => Err(String::from("Nobody to reply to yet."))
+        };
+    }
+
+    Ok(None)
+}
+
+// Messages from channels other than the one we are typing in are tagged with their channel
+fn channel_prefix(from: &str, current: &str) -> String {
+    if from == current {
+        String::new()
+    }
+    else {
+        format!("[{}] ", from)
+    }
+}
+
+// Sends a packet if we have a connection. Packets sent while it is still being set up go out once it is.
+fn send_packet<T: Transport>(transport: &mut Option<T>, packet: &Message, messages: &mut Vec<String>) {
+    let result: Result<(), Error> = match *transport {
+        Some(ref mut transport) => transport.send(packet),
+        None => return
+    };
+
+    if let Err(e) = result {
+        warn!("Dropping outgoing packet {:?}: {}", packet, e);
+        messages.push(format!("Error: {}", e));
+    }
+}
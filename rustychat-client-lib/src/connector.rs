@@ -0,0 +1,90 @@
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use doosknet::*;
+use arg_value;
+
+const DEFAULT_TIMEOUT_SECONDS: u64 = 45;
+
+// Opens connections to a server, each a doosknet Transport: the first one, and another each time the last one is lost
+pub trait Connector {
+    type Transport: Transport;
+
+    // Errors start with what we tried to connect to, e.g. "127.0.0.1:7667: Connection refused"
+    fn connect(&mut self) -> Result<Self::Transport, String>;
+}
+
+// Connects over TCP, with TLS if there is a config for it
+pub struct TcpConnector {
+    pub host: String,
+    pub tls_config: Option<Arc<tls::ClientConfig>>,
+    // The name the server's certificate must be valid for
+    pub tls_name: String,
+    // Give up on the server if it hasn't been heard from in this many seconds, pinging it a few times along the way.
+    // 0 to never give up.
+    pub timeout: u64
+}
+
+impl TcpConnector {
+    // `<host:port> --timeout <seconds>` and the TLS options. Errors say which option can't be used.
+    pub fn from_args(args: &[String]) -> Result<TcpConnector, String> {
+        let mut host: String = String::from("127.0.0.1:7667");
+        if args.len() >= 2 && !args[1].starts_with("--") {
+            host = args[1].clone();
+        }
+
+        // Optional TLS, verified against a CA (--tls-ca <path>) or a pinned certificate (--tls-fingerprint <sha256>)
+        let tls_config: Option<Arc<tls::ClientConfig>> = match (arg_value(args, "--tls-ca"), arg_value(args, "--tls-fingerprint")) {
+            (Some(ca), None) => Some(tls::load_client_config(Path::new(&ca)).map_err(|e| format!("--tls-ca: {}", e))?),
+            (None, Some(fingerprint)) => Some(tls::pinned_client_config(&fingerprint).map_err(|e| format!("--tls-fingerprint: {}", e))?),
+            (None, None) => None,
+            _ => return Err(String::from("Use either --tls-ca or --tls-fingerprint, not both"))
+        };
+
+        // Defaults to the host we connect to
+        let tls_name: String = arg_value(args, "--tls-name")
+            .unwrap_or_else(|| {
+                let name: &str = host.rsplit_once(':').map_or(host.as_str(), |(name, _)| name);
+                name.trim_matches(|c| c == '[' || c == ']').to_string()
+            });
+
+        let timeout: u64 = match arg_value(args, "--timeout") {
+            Some(timeout) => timeout.parse().map_err(|_| format!("Invalid value for --timeout: {}", timeout))?,
+            None => DEFAULT_TIMEOUT_SECONDS
+        };
+
+        Ok(TcpConnector {
+            tls_config,
+            tls_name,
+            timeout,
+            host
+        })
+    }
+}
+
+impl Connector for TcpConnector {
+    type Transport = Client;
+
+    // Looks the host up again each time, in case its address has changed
+    fn connect(&mut self) -> Result<Client, String> {
+        let addr: SocketAddr = self.host.to_socket_addrs()
+            .map_err(|e| format!("{}: {}", self.host, e))?
+            .next()
+            .ok_or_else(|| format!("{}: it has no address", self.host))?;
+
+        let mut client: Client = match self.tls_config {
+            Some(ref config) => Client::connect_tls(&addr, &self.tls_name, config.clone()),
+            None => Client::connect(&addr)
+        }.map_err(|e| format!("{}: {}", self.host, e))?;
+
+        if self.timeout > 0 {
+            client.set_heartbeat(Some(Heartbeat {
+                interval: Duration::from_millis(self.timeout * 1000 / 3),
+                timeout: Duration::from_secs(self.timeout)
+            }));
+        }
+
+        Ok(client)
+    }
+}
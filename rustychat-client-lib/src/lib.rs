@@ -0,0 +1,23 @@
+#[macro_use]
+extern crate log;
+
+extern crate doosknet;
+pub extern crate rustychat_logger as logger;
+
+mod backoff;
+pub mod chat;
+pub mod connector;
+pub mod memory;
+
+pub use chat::{ChatClient, ChatEvent, ConnectionState};
+pub use doosknet::Transport;
+pub use connector::{Connector, TcpConnector};
+pub use memory::{MemoryServer, MemoryConnector, MemoryTransport};
+
+// The value after `name` on the command line, e.g. arg_value(&args, "--nick")
+pub fn arg_value(args: &[String], name: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+}
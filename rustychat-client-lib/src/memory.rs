@@ -0,0 +1,136 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::rc::Rc;
+use std::time::Duration;
+use doosknet::*;
+use connector::Connector;
+
+// Stands in for a server, so a ChatClient can be driven without a network, e.g. in tests.
+// Whatever is sent from here arrives at the client on its next update, and whatever the client sends
+// is kept until `take_sent`.
+#[derive(Clone, Default)]
+pub struct MemoryServer {
+    state: Rc<RefCell<MemoryState>>
+}
+
+#[derive(Default)]
+struct MemoryState {
+    // How many connections have been made. Transports from earlier ones no longer work.
+    connections: usize,
+    is_open: bool,
+    is_refusing: bool,
+    incoming: VecDeque<NetworkEvent>,
+    sent: Vec<Message>
+}
+
+impl MemoryServer {
+    pub fn new() -> MemoryServer {
+        MemoryServer::default()
+    }
+
+    pub fn connector(&self) -> MemoryConnector {
+        MemoryConnector {
+            server: self.clone()
+        }
+    }
+
+    // Delivered to the client if it is connected, otherwise dropped
+    pub fn send(&self, packet: Message) {
+        let bytes: usize = serialize_packet(&packet).map_or(0, |data| data.len());
+        self.send_event(NetworkEvent::Message(SERVER_CONN, packet, bytes));
+    }
+
+    // Anything else the endpoint could report, such as a packet it failed to decode
+    pub fn send_event(&self, event: NetworkEvent) {
+        let mut state = self.state.borrow_mut();
+        if state.is_open {
+            state.incoming.push_back(event);
+        }
+    }
+
+    // Closes the connection, the same as the server going away
+    pub fn disconnect(&self) {
+        let mut state = self.state.borrow_mut();
+        if state.is_open {
+            state.is_open = false;
+            state.incoming.push_back(NetworkEvent::Disconnected(SERVER_CONN));
+        }
+    }
+
+    // While set, attempts to connect fail
+    pub fn refuse_connections(&self, is_refusing: bool) {
+        self.state.borrow_mut().is_refusing = is_refusing;
+    }
+
+    // Everything the client has sent since the last call, oldest first
+    pub fn take_sent(&self) -> Vec<Message> {
+        self.state.borrow_mut().sent.drain(..).collect()
+    }
+
+    pub fn connections(&self) -> usize {
+        self.state.borrow().connections
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state.borrow().is_open
+    }
+}
+
+pub struct MemoryConnector {
+    server: MemoryServer
+}
+
+impl Connector for MemoryConnector {
+    type Transport = MemoryTransport;
+
+    fn connect(&mut self) -> Result<MemoryTransport, String> {
+        let mut state = self.server.state.borrow_mut();
+        if state.is_refusing {
+            return Err(String::from("the memory server: Connection refused"));
+        }
+
+        state.connections += 1;
+        state.is_open = true;
+        state.incoming.clear();
+        state.incoming.push_back(NetworkEvent::Connected(SERVER_CONN));
+
+        Ok(MemoryTransport {
+            server: self.server.clone(),
+            connection: state.connections
+        })
+    }
+}
+
+pub struct MemoryTransport {
+    server: MemoryServer,
+    connection: usize
+}
+
+impl MemoryTransport {
+    fn is_current(&self, state: &MemoryState) -> bool {
+        state.connections == self.connection
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn send(&mut self, packet: &Message) -> Result<(), Error> {
+        let mut state = self.server.state.borrow_mut();
+        if !self.is_current(&state) || !state.is_open {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::NotConnected, "The connection is closed")));
+        }
+
+        state.sent.push(packet.clone());
+        Ok(())
+    }
+
+    // Never waits. Everything sent from the server so far is already here.
+    fn poll(&mut self, _timeout: Option<Duration>) -> Result<Vec<NetworkEvent>, Error> {
+        let mut state = self.server.state.borrow_mut();
+        if !self.is_current(&state) {
+            return Ok(Vec::new());
+        }
+
+        Ok(state.incoming.drain(..).collect())
+    }
+}
@@ -0,0 +1,311 @@
+extern crate doosknet;
+extern crate rustychat_client;
+
+use std::time::Duration;
+use doosknet::*;
+use rustychat_client::*;
+
+// A client for `server` that reconnects straight away, so tests never wait
+fn client(server: &MemoryServer, nickname: Option<&str>) -> ChatClient<MemoryConnector> {
+    let mut client = ChatClient::new(server.connector(), nickname.map(String::from), None);
+    client.set_reconnect_delays(Duration::from_secs(0), Duration::from_secs(0));
+    client
+}
+
+fn update(client: &mut ChatClient<MemoryConnector>) -> Vec<ChatEvent> {
+    client.update(Duration::from_secs(0))
+}
+
+// Connected, welcomed as `nickname` and in #general
+fn logged_in(server: &MemoryServer, nickname: &str) -> ChatClient<MemoryConnector> {
+    let mut client = client(server, Some(nickname));
+    update(&mut client);
+
+    server.send(Message::welcome(nickname, Vec::new(), "Test", "Hello!"));
+    server.send(Message::Joined { channel: String::from("#general"), topic: String::new() });
+    update(&mut client);
+    server.take_sent();
+
+    client
+}
+
+#[test]
+fn clients_introduce_themselves_once_connected() {
+    let server = MemoryServer::new();
+    let mut client = client(&server, Some("alice"));
+    assert!(matches!(client.state(), ConnectionState::Offline(Some(_))));
+
+    assert_eq!(update(&mut client), vec![ChatEvent::Connected]);
+    assert_eq!(client.state(), ConnectionState::Connecting);
+    assert_eq!(server.take_sent(), vec![Message::hello("alice")]);
+
+    server.send(Message::welcome("alice", Vec::new(), "Test", "Hello!"));
+    let events: Vec<ChatEvent> = update(&mut client);
+
+    assert_eq!(events[0], ChatEvent::Welcomed { username: String::from("alice") });
+    assert_eq!(client.state(), ConnectionState::Online);
+    assert_eq!(client.username(), Some("alice"));
+    assert_eq!(client.messages, vec!["Connected to Test as alice.", "Hello!"]);
+}
+
+#[test]
+fn the_first_line_is_the_nickname_without_one() {
+    let server = MemoryServer::new();
+    let mut client = client(&server, None);
+    update(&mut client);
+
+    assert_eq!(client.messages, vec!["Choose a nickname and press Enter:"]);
+    assert!(server.take_sent().is_empty());
+
+    client.submit("bob");
+    assert_eq!(server.take_sent(), vec![Message::hello("bob")]);
+
+    server.send(Message::nickname_rejected("bob", "bob is taken."));
+    update(&mut client);
+    assert_eq!(client.messages[1..], ["Error: bob is taken.", "Choose another nickname:"]);
+
+    client.submit("bobby");
+    assert_eq!(server.take_sent(), vec![Message::hello("bobby")]);
+}
+
+#[test]
+fn lines_go_to_the_current_channel() {
+    let server = MemoryServer::new();
+    let mut client = logged_in(&server, "alice");
+
+    client.submit("hi all");
+    assert_eq!(server.take_sent(), vec![Message::chat("#general", "alice", "hi all")]);
+
+    server.send(Message::Joined { channel: String::from("#rust"), topic: String::from("Crabs") });
+    server.send(Message::chat("#general", "bob", "hello"));
+    update(&mut client);
+
+    assert_eq!(client.channel(), "#rust");
+    assert_eq!(client.channels(), ["#general", "#rust"]);
+    assert_eq!(client.messages[client.messages.len() - 2..], ["The topic of #rust is: Crabs", "[#general] bob says \"hello\""]);
+
+    client.next_channel();
+    client.submit("back again");
+    assert_eq!(server.take_sent(), vec![Message::chat("#general", "alice", "back again")]);
+}
+
+#[test]
+fn leaving_the_current_channel_switches_to_another() {
+    let server = MemoryServer::new();
+    let mut client = logged_in(&server, "alice");
+
+    server.send(Message::Joined { channel: String::from("#rust"), topic: String::new() });
+    server.send(Message::Leave { channel: String::from("#rust"), username: String::from("alice") });
+    update(&mut client);
+
+    assert_eq!(client.channel(), "#general");
+    assert_eq!(client.channels(), ["#general"]);
+}
+
+#[test]
+fn everything_received_is_passed_on() {
+    let server = MemoryServer::new();
+    let mut client = logged_in(&server, "alice");
+
+    server.send(Message::notice("Restarting soon"));
+    server.send(Message::action("#general", "bob", "waves"));
+    let events: Vec<ChatEvent> = update(&mut client);
+
+    assert_eq!(events, vec![
+        ChatEvent::Received(Message::notice("Restarting soon")),
+        ChatEvent::Received(Message::action("#general", "bob", "waves"))
+    ]);
+    assert_eq!(client.messages[client.messages.len() - 2..], ["[Restarting soon]", "* bob waves"]);
+}
+
+#[test]
+fn replies_go_to_the_last_direct_message() {
+    let server = MemoryServer::new();
+    let mut client = logged_in(&server, "alice");
+
+    client.submit("/r anyone?");
+    assert!(server.take_sent().is_empty());
+    assert_eq!(client.messages.last().unwrap(), "Error: Nobody to reply to yet.");
+
+    server.send(Message::direct_message("bob", "alice", "psst"));
+    update(&mut client);
+    assert_eq!(client.messages.last().unwrap(), "[DM from bob] psst");
+
+    client.submit("/r what?");
+    client.submit("/msg carol hi there");
+    assert_eq!(server.take_sent(), vec![
+        Message::direct_message("alice", "bob", "what?"),
+        Message::direct_message("alice", "carol", "hi there")
+    ]);
+}
+
+#[test]
+fn history_is_fetched_from_the_oldest_message_seen() {
+    let server = MemoryServer::new();
+    let mut client = logged_in(&server, "alice");
+
+    server.send(Message::HistoryEntry {
+        channel: String::from("#general"),
+        id: 42,
+        timestamp: 3600 * 13 + 60 * 5,
+        sender: String::from("bob"),
+        message: String::from("earlier"),
+        is_action: false
+    });
+    server.send(Message::HistoryEnd { channel: String::from("#general"), has_more: true });
+    update(&mut client);
+    assert_eq!(client.messages.last().unwrap(), "[13:05] bob says \"earlier\"");
+
+    client.request_history();
+    assert_eq!(server.take_sent(), vec![Message::HistoryRequest { channel: String::from("#general"), before: 42, count: 20 }]);
+
+    server.send(Message::HistoryEnd { channel: String::from("#general"), has_more: false });
+    update(&mut client);
+
+    client.submit("/history");
+    assert!(server.take_sent().is_empty());
+    assert_eq!(client.messages.last().unwrap(), "Error: There is nothing older in #general.");
+}
+
+#[test]
+fn registered_nicknames_ask_for_a_password() {
+    let server = MemoryServer::new();
+    let mut client = client(&server, Some("alice"));
+    update(&mut client);
+    server.take_sent();
+
+    server.send(Message::auth_required("alice", ""));
+    update(&mut client);
+    assert_eq!(client.mask_input("hunter2"), "*******");
+
+    client.submit("hunter2");
+    assert_eq!(server.take_sent(), vec![Message::login("alice", "hunter2")]);
+    assert_eq!(client.mask_input("hunter2"), "hunter2");
+
+    // Logs in again by itself after reconnecting
+    server.disconnect();
+    update(&mut client);
+    update(&mut client);
+    server.send(Message::auth_required("alice", ""));
+    update(&mut client);
+    assert_eq!(server.take_sent(), vec![Message::hello("alice"), Message::login("alice", "hunter2")]);
+}
+
+#[test]
+fn lines_typed_offline_are_sent_after_reconnecting() {
+    let server = MemoryServer::new();
+    let mut client = logged_in(&server, "alice");
+
+    server.disconnect();
+    let events: Vec<ChatEvent> = update(&mut client);
+    assert_eq!(events, vec![ChatEvent::Disconnected {
+        reason: String::from("Disconnected from the server."),
+        retry_in: Some(Duration::from_secs(0))
+    }]);
+    assert_eq!(client.username(), None);
+
+    client.submit("anyone there?");
+    client.say("#general", "from a bot").unwrap();
+    assert_eq!(client.queued().len(), 2);
+    assert_eq!(client.status(), "[offline, 2 queued, retrying in 1s] ");
+
+    // Rejoins, then sends the queue in order
+    update(&mut client);
+    assert_eq!(server.connections(), 2);
+    server.send(Message::welcome("alice", Vec::new(), "Test", "Hello!"));
+    update(&mut client);
+
+    assert_eq!(server.take_sent(), vec![
+        Message::hello("alice"),
+        Message::chat("#general", "alice", "/join #general"),
+        Message::chat("#general", "alice", "anyone there?"),
+        Message::chat("#general", "alice", "from a bot")
+    ]);
+    assert!(client.queued().is_empty());
+    assert_eq!(client.state(), ConnectionState::Online);
+}
+
+#[test]
+fn connection_failures_are_retried() {
+    let server = MemoryServer::new();
+    server.refuse_connections(true);
+    let mut client = client(&server, Some("alice"));
+
+    let events: Vec<ChatEvent> = update(&mut client);
+    assert_eq!(events, vec![ChatEvent::Disconnected {
+        reason: String::from("Couldn't connect to the memory server: Connection refused"),
+        retry_in: Some(Duration::from_secs(0))
+    }]);
+    assert_eq!(server.connections(), 0);
+
+    server.refuse_connections(false);
+    update(&mut client);
+    assert!(server.is_connected());
+    assert_eq!(server.take_sent(), vec![Message::hello("alice")]);
+}
+
+#[test]
+fn rejected_clients_stay_offline() {
+    let server = MemoryServer::new();
+    let mut client = client(&server, Some("alice"));
+    update(&mut client);
+
+    server.send(Message::rejected("You are banned."));
+    server.disconnect();
+    let events: Vec<ChatEvent> = update(&mut client);
+
+    assert_eq!(events.last().unwrap(), &ChatEvent::Disconnected {
+        reason: String::from("Disconnected from the server."),
+        retry_in: None
+    });
+    assert_eq!(client.state(), ConnectionState::Offline(None));
+
+    update(&mut client);
+    assert_eq!(server.connections(), 1);
+    assert!(client.say("#general", "hello?").is_err());
+}
+
+#[test]
+fn servers_on_another_version_are_not_retried() {
+    let server = MemoryServer::new();
+    let mut client = client(&server, Some("alice"));
+    update(&mut client);
+
+    server.send_event(NetworkEvent::DecodeFailed(SERVER_CONN, DecodeError::UnsupportedVersion(PROTOCOL_VERSION + 1)));
+    server.disconnect();
+    let events: Vec<ChatEvent> = update(&mut client);
+
+    assert!(client.messages.iter().any(|line| line.contains(&format!("protocol version {}", PROTOCOL_VERSION + 1))));
+    assert_eq!(events.last().unwrap(), &ChatEvent::Disconnected {
+        reason: String::from("Disconnected from the server."),
+        retry_in: None
+    });
+    assert_eq!(client.state(), ConnectionState::Offline(None));
+}
+
+#[test]
+fn nicknames_rejected_after_reconnecting_can_be_replaced() {
+    let server = MemoryServer::new();
+    let mut client = logged_in(&server, "alice");
+
+    // The server still holds "alice" for the connection we lost
+    server.disconnect();
+    update(&mut client);
+    update(&mut client);
+    assert_eq!(server.take_sent(), vec![Message::hello("alice")]);
+    server.send(Message::nickname_rejected("alice", "alice is taken."));
+    update(&mut client);
+    assert_eq!(client.messages[client.messages.len() - 2..], ["Error: alice is taken.", "Choose another nickname:"]);
+
+    client.submit("alice2");
+    assert!(client.queued().is_empty());
+    assert_eq!(server.take_sent(), vec![Message::hello("alice2")]);
+
+    // Once welcomed, lines are chat again, and the channels are rejoined under the new name
+    server.send(Message::welcome("alice2", Vec::new(), "Test", "Hello!"));
+    update(&mut client);
+    assert_eq!(server.take_sent(), vec![Message::chat("#general", "alice2", "/join #general")]);
+
+    client.submit("back");
+    assert_eq!(server.take_sent(), vec![Message::chat("#general", "alice2", "back")]);
+}
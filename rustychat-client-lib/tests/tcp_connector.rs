@@ -0,0 +1,25 @@
+extern crate rustychat_client;
+
+use rustychat_client::*;
+
+fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(String::from).collect()
+}
+
+#[test]
+fn options_fill_in_the_connector() {
+    let connector: TcpConnector = TcpConnector::from_args(&args("client [::1]:7000 --timeout 10")).unwrap();
+
+    assert_eq!(connector.host, "[::1]:7000");
+    assert_eq!(connector.tls_name, "::1");
+    assert_eq!(connector.timeout, 10);
+    assert!(connector.tls_config.is_none());
+}
+
+#[test]
+fn unusable_options_are_errors() {
+    assert_eq!(TcpConnector::from_args(&args("client --timeout soon")).err().unwrap(), "Invalid value for --timeout: soon");
+    assert!(TcpConnector::from_args(&args("client --tls-fingerprint nope")).err().unwrap().starts_with("--tls-fingerprint: "));
+    assert!(TcpConnector::from_args(&args("client --tls-ca /nonexistent/ca.pem")).err().unwrap().starts_with("--tls-ca: "));
+    assert!(TcpConnector::from_args(&args("client --tls-ca ca.pem --tls-fingerprint AB")).is_err());
+}
@@ -0,0 +1,287 @@
+// The log output shared by the server and the clients: text on stderr, and optionally JSON lines in a rotated file
+extern crate log;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate humantime;
+
+use std::cmp::Reverse;
+use std::ffi::OsString;
+use std::fmt::Write as FmtWrite;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+use std::time::SystemTime;
+use log::{Log, Metadata, Record, LevelFilter};
+use log::kv::{self, Key, Value, VisitSource};
+use serde_json::{Map, Value as JsonValue};
+
+pub static LOG_LEVELS: &[&str] = &["off", "error", "warn", "info", "debug", "trace"];
+
+// Which records are logged: a default level, and levels for particular modules.
+// Written as "info,doosknet=debug,chat=off". A module's level also covers the modules inside it.
+#[derive(Debug)]
+pub struct Filter {
+    default: LevelFilter,
+    modules: Vec<(String, LevelFilter)>
+}
+
+impl Filter {
+    const DEFAULT: Filter = Filter {
+        default: LevelFilter::Info,
+        modules: Vec::new()
+    };
+
+    pub fn parse(spec: &str) -> Result<Filter, String> {
+        let mut filter: Filter = Filter::DEFAULT;
+
+        for part in spec.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            match part.find('=') {
+                Some(split) => {
+                    let module: &str = part[..split].trim();
+                    if module.is_empty() {
+                        return Err(format!("{} is missing a module name", part));
+                    }
+
+                    filter.modules.push((String::from(module), parse_level(&part[split + 1..])?));
+                },
+                None => filter.default = parse_level(part)?
+            }
+        }
+
+        // Longest first, so the most specific module wins
+        filter.modules.sort_by_key(|module| Reverse(module.0.len()));
+
+        Ok(filter)
+    }
+
+    // The level records from `target` are logged at
+    pub fn level(&self, target: &str) -> LevelFilter {
+        self.modules.iter()
+            .find(|&(module, _)| target == module || (target.starts_with(module.as_str()) && target[module.len()..].starts_with("::")))
+            .map_or(self.default, |&(_, level)| level)
+    }
+
+    // The most verbose level anything is logged at, so the log macros can skip the rest cheaply
+    pub fn max_level(&self) -> LevelFilter {
+        self.modules.iter().map(|&(_, level)| level).fold(self.default, Ord::max)
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    let level: &str = level.trim();
+    if !LOG_LEVELS.contains(&level) {
+        return Err(format!("Unknown log level {}, expected one of {}", level, LOG_LEVELS.join(", ")));
+    }
+
+    level.parse().map_err(|_| format!("Unknown log level {}", level))
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogFileConfig {
+    pub path: PathBuf,
+    // Size at which the file is rotated. 0 lets it grow forever.
+    pub max_bytes: u64,
+    // How many rotated files to keep
+    pub keep: usize
+}
+
+impl Default for LogFileConfig {
+    fn default() -> Self {
+        LogFileConfig {
+            path: PathBuf::from("logs/rustychat.log"),
+            max_bytes: 10 * 1024 * 1024,
+            keep: 5
+        }
+    }
+}
+
+// JSON lines. Once the file would grow past `max_bytes` it is renamed to `<path>.1`, `<path>.1` to `<path>.2`
+// and so on, keeping `keep` old files.
+pub struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    keep: usize
+}
+
+impl LogFile {
+    pub fn open(config: &LogFileConfig) -> io::Result<LogFile> {
+        if let Some(parent) = config.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file: File = OpenOptions::new().create(true).append(true).open(&config.path)?;
+
+        Ok(LogFile {
+            path: config.path.clone(),
+            size: file.metadata()?.len(),
+            file,
+            max_bytes: config.max_bytes,
+            keep: config.keep
+        })
+    }
+
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let length: u64 = line.len() as u64 + 1;
+        if self.max_bytes > 0 && self.size > 0 && self.size + length > self.max_bytes {
+            self.rotate()?;
+        }
+
+        writeln!(self.file, "{}", line)?;
+        self.size += length;
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for i in (1..self.keep).rev() {
+            match fs::rename(self.numbered(i), self.numbered(i + 1)) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+                result => result?
+            }
+        }
+
+        if self.keep > 0 {
+            fs::rename(&self.path, self.numbered(1))?;
+        }
+
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+
+    fn numbered(&self, i: usize) -> PathBuf {
+        let mut path: OsString = self.path.clone().into_os_string();
+        path.push(format!(".{}", i));
+        PathBuf::from(path)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+// Writes every record to stderr as text, and to the log file as JSON if there is one
+struct Logger {
+    filter: RwLock<Filter>,
+    file: Mutex<Option<LogFile>>
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        match self.filter.read() {
+            Ok(filter) => metadata.level() <= filter.level(metadata.target()),
+            Err(_) => false
+        }
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let timestamp = humantime::format_rfc3339_millis(SystemTime::now());
+
+        let mut fields: Fields = Fields(Vec::new());
+        let _ = record.key_values().visit(&mut fields);
+
+        let mut line: String = format!("{} {:<5} {}: {}", timestamp, record.level(), record.target(), record.args());
+        for (key, value) in &fields.0 {
+            let _ = match *value {
+                JsonValue::String(ref text) => write!(line, " {}={}", key, text),
+                ref value => write!(line, " {}={}", key, value)
+            };
+        }
+
+        let _ = writeln!(io::stderr(), "{}", line);
+
+        if let Ok(mut file) = self.file.lock() {
+            if let Some(ref mut file) = *file {
+                let mut object: Map<String, JsonValue> = Map::new();
+                object.insert(String::from("timestamp"), JsonValue::from(timestamp.to_string()));
+                object.insert(String::from("level"), JsonValue::from(record.level().as_str()));
+                object.insert(String::from("target"), JsonValue::from(record.target()));
+                object.insert(String::from("message"), JsonValue::from(record.args().to_string()));
+
+                // Fields sit next to the message, but can't replace any of the above
+                for (key, value) in fields.0 {
+                    object.entry(key).or_insert(value);
+                }
+
+                let written = serde_json::to_string(&object)
+                    .map_err(io::Error::from)
+                    .and_then(|json| file.write_line(&json));
+
+                if let Err(e) = written {
+                    let _ = writeln!(io::stderr(), "Failed to write to {}: {}", file.path.display(), e);
+                }
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            if let Some(ref mut file) = *file {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+// A record's key-value fields. Numbers and booleans stay as they are in the JSON, anything else becomes a string.
+struct Fields(Vec<(String, JsonValue)>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value: JsonValue = if let Some(number) = value.to_u64() {
+            JsonValue::from(number)
+        }
+        else if let Some(number) = value.to_i64() {
+            JsonValue::from(number)
+        }
+        else if let Some(boolean) = value.to_bool() {
+            JsonValue::from(boolean)
+        }
+        else {
+            JsonValue::from(value.to_string())
+        };
+
+        self.0.push((key.to_string(), value));
+        Ok(())
+    }
+}
+
+static LOGGER: Logger = Logger {
+    filter: RwLock::new(Filter::DEFAULT),
+    file: Mutex::new(None)
+};
+
+// `level` is a filter as described on Filter. Also logs to `file`, if given.
+pub fn init(level: &str, file: Option<&LogFileConfig>) -> Result<(), String> {
+    if let Some(config) = file {
+        let file: LogFile = LogFile::open(config).map_err(|e| format!("Failed to open {}: {}", config.path.display(), e))?;
+        if let Ok(mut current) = LOGGER.file.lock() {
+            *current = Some(file);
+        }
+    }
+
+    set_level(level)?;
+    log::set_logger(&LOGGER).map_err(|e| e.to_string())
+}
+
+pub fn set_level(level: &str) -> Result<(), String> {
+    let filter: Filter = Filter::parse(level)?;
+    log::set_max_level(filter.max_level());
+
+    if let Ok(mut current) = LOGGER.filter.write() {
+        *current = filter;
+    }
+
+    Ok(())
+}
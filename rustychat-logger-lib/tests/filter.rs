@@ -0,0 +1,49 @@
+extern crate log;
+extern crate rustychat_logger;
+
+use log::LevelFilter;
+use rustychat_logger::Filter;
+
+fn filter(spec: &str) -> Filter {
+    Filter::parse(spec).unwrap()
+}
+
+#[test]
+fn the_default_level_is_info() {
+    assert_eq!(filter("").level("anything"), LevelFilter::Info);
+    assert_eq!(filter(" , ").level("anything"), LevelFilter::Info);
+    assert_eq!(filter("debug").level("anything"), LevelFilter::Debug);
+}
+
+#[test]
+fn module_levels_cover_the_modules_inside_them() {
+    let filter: Filter = filter("warn, doosknet=debug, doosknet::endpoint=trace, chat=off");
+
+    assert_eq!(filter.level("rustychat_server"), LevelFilter::Warn);
+    assert_eq!(filter.level("doosknet"), LevelFilter::Debug);
+    assert_eq!(filter.level("doosknet::buffer"), LevelFilter::Debug);
+    assert_eq!(filter.level("doosknet::endpoint"), LevelFilter::Trace);
+    assert_eq!(filter.level("chat"), LevelFilter::Off);
+
+    // Only whole module names match
+    assert_eq!(filter.level("doosknetwork"), LevelFilter::Warn);
+    assert_eq!(filter.level("chatter"), LevelFilter::Warn);
+
+    assert_eq!(filter.max_level(), LevelFilter::Trace);
+}
+
+#[test]
+fn later_levels_override_earlier_ones() {
+    assert_eq!(filter("error,info").level("anything"), LevelFilter::Info);
+    assert_eq!(filter("off").max_level(), LevelFilter::Off);
+}
+
+#[test]
+fn unknown_levels_and_missing_modules_are_rejected() {
+    for invalid in &["loud", "info,doosknet=loud", "=debug", "info, =debug", "INFO", "doosknet="] {
+        assert!(Filter::parse(invalid).is_err(), "{} parsed", invalid);
+    }
+
+    let message: String = Filter::parse("verbose").unwrap_err();
+    assert!(message.contains("off, error, warn, info, debug, trace"), "{}", message);
+}
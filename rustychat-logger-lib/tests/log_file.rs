@@ -0,0 +1,101 @@
+extern crate rustychat_logger;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use rustychat_logger::{LogFile, LogFileConfig};
+
+// A fresh directory per test, removed again when dropped
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let path: PathBuf = env::temp_dir().join(format!("rustychat-logger-test-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        TempDir(path)
+    }
+
+    fn read(&self, name: &str) -> Option<String> {
+        fs::read_to_string(self.0.join(name)).ok()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn open(dir: &TempDir, max_bytes: u64, keep: usize) -> LogFile {
+    LogFile::open(&LogFileConfig { path: dir.0.join("logs").join("test.log"), max_bytes, keep }).unwrap()
+}
+
+#[test]
+fn files_are_rotated_before_they_grow_too_large() {
+    let dir = TempDir::new("rotate");
+    let mut file: LogFile = open(&dir, 10, 2);
+
+    // Each line is 5 bytes with its newline, so two fit
+    for line in &["aaaa", "bbbb", "cccc", "dddd", "eeee", "ffff", "gggg"] {
+        file.write_line(line).unwrap();
+    }
+    file.flush().unwrap();
+
+    assert_eq!(dir.read("logs/test.log").as_deref(), Some("gggg\n"));
+    assert_eq!(dir.read("logs/test.log.1").as_deref(), Some("eeee\nffff\n"));
+    assert_eq!(dir.read("logs/test.log.2").as_deref(), Some("cccc\ndddd\n"));
+    assert_eq!(dir.read("logs/test.log.3"), None);
+}
+
+#[test]
+fn lines_longer_than_the_limit_still_get_written() {
+    let dir = TempDir::new("long");
+    let mut file: LogFile = open(&dir, 4, 1);
+
+    file.write_line("longer than four").unwrap();
+    file.write_line("again").unwrap();
+    file.flush().unwrap();
+
+    assert_eq!(dir.read("logs/test.log").as_deref(), Some("again\n"));
+    assert_eq!(dir.read("logs/test.log.1").as_deref(), Some("longer than four\n"));
+}
+
+#[test]
+fn reopening_appends_and_counts_what_is_already_there() {
+    let dir = TempDir::new("reopen");
+    {
+        let mut file: LogFile = open(&dir, 10, 1);
+        file.write_line("aaaa").unwrap();
+    }
+
+    let mut file: LogFile = open(&dir, 10, 1);
+    file.write_line("bbbb").unwrap();
+    file.write_line("cccc").unwrap();
+    file.flush().unwrap();
+
+    assert_eq!(dir.read("logs/test.log.1").as_deref(), Some("aaaa\nbbbb\n"));
+    assert_eq!(dir.read("logs/test.log").as_deref(), Some("cccc\n"));
+}
+
+#[test]
+fn without_a_limit_or_old_files_nothing_is_kept_aside() {
+    let dir = TempDir::new("unlimited");
+    let mut file: LogFile = open(&dir, 0, 2);
+    for _ in 0..100 {
+        file.write_line("line").unwrap();
+    }
+    file.flush().unwrap();
+    assert_eq!(dir.read("logs/test.log").map(|text| text.len()), Some(500));
+    assert_eq!(dir.read("logs/test.log.1"), None);
+
+    // keep = 0 starts the file over instead
+    let dir = TempDir::new("keep-none");
+    let mut file: LogFile = open(&dir, 10, 0);
+    for line in &["aaaa", "bbbb", "cccc"] {
+        file.write_line(line).unwrap();
+    }
+    file.flush().unwrap();
+    assert_eq!(dir.read("logs/test.log").as_deref(), Some("cccc\n"));
+    assert_eq!(dir.read("logs/test.log.1"), None);
+}
@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::SaltString;
+use argon2::password_hash::rand_core::OsRng;
+use serde_json;
+use roles::Role;
+
+pub const MIN_PASSWORD_LENGTH: usize = 6;
+pub const MAX_PASSWORD_LENGTH: usize = 64;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Account {
+    // The nickname the account owns, as it was registered
+    pub name: String,
+    // Argon2id hash in PHC string format, salt included
+    pub password_hash: String,
+    // Seconds since the Unix epoch
+    pub created: u64,
+    // Server-wide role, handed out with /grant
+    #[serde(default)]
+    pub role: Role
+}
+
+// Registered accounts, kept in `<data_dir>/accounts.json`.
+// Each account owns the nickname it was registered under.
+pub struct Accounts {
+    path: PathBuf,
+    accounts: BTreeMap<String, Account>
+}
+
+impl Accounts {
+    pub fn open(data_dir: &Path) -> io::Result<Accounts> {
+        let path: PathBuf = data_dir.join("accounts.json");
+
+        let accounts: BTreeMap<String, Account> = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e)
+        };
+
+        Ok(Accounts {
+            path,
+            accounts
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Account> {
+        self.accounts.get(&key(name))
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.accounts.contains_key(&key(name))
+    }
+
+    pub fn register(&mut self, name: &str, password: &str) -> Result<(), String> {
+        if self.exists(name) {
+            return Err(format!("{} is already registered.", name));
+        }
+
+        let account = Account {
+            name: String::from(name),
+            password_hash: hash_password(password)?,
+            created: SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0),
+            role: Role::User
+        };
+
+        self.accounts.insert(key(name), account);
+        self.save()
+    }
+
+    // Whether `password` is right for the account. False if there is no such account.
+    pub fn verify(&self, name: &str, password: &str) -> bool {
+        let account: &Account = match self.get(name) {
+            Some(account) => account,
+            None => return false
+        };
+
+        match PasswordHash::new(&account.password_hash) {
+            Ok(hash) => Argon2::default().verify_password(password.as_bytes(), &hash).is_ok(),
+            Err(e) => {
+                warn!("Stored password hash for {} is unreadable: {}", account.name, e);
+                false
+            }
+        }
+    }
+
+    pub fn set_password(&mut self, name: &str, password: &str) -> Result<(), String> {
+        let password_hash: String = hash_password(password)?;
+
+        match self.accounts.get_mut(&key(name)) {
+            Some(account) => account.password_hash = password_hash,
+            None => return Err(format!("{} is not registered.", name))
+        }
+
+        self.save()
+    }
+
+    pub fn set_role(&mut self, name: &str, role: Role) -> Result<(), String> {
+        match self.accounts.get_mut(&key(name)) {
+            Some(account) => account.role = role,
+            None => return Err(format!("{} is not registered.", name))
+        }
+
+        self.save()
+    }
+
+    // Writes a fresh copy next to the file and swaps it in, so a crash can't leave half a file behind
+    // Written to a temporary file first and renamed over the old one, so a crash never leaves half a file
+    fn save(&self) -> Result<(), String> {
+        let temp_path: PathBuf = self.path.with_extension("json.tmp");
+
+        let result = serde_json::to_string_pretty(&self.accounts)
+            .map_err(io::Error::from)
+            .and_then(|text| write_private(&temp_path, &text))
+            .and_then(|_| fs::rename(&temp_path, &self.path));
+
+        result.map_err(|e| {
+            error!("Failed to save accounts to {}: {}", self.path.display(), e);
+            String::from("The server could not save your account. Please try again later.")
+        })
+    }
+}
+
+// Creates `path` afresh, readable only by us where the platform allows it, since it holds password hashes
+fn write_private(path: &Path, text: &str) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+        result => result?
+    }
+
+    let mut options: OpenOptions = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut file: File = options.open(path)?;
+    file.write_all(text.as_bytes())?;
+    file.sync_all()
+}
+
+pub fn validate_password(password: &str) -> Result<(), String> {
+    if password.len() < MIN_PASSWORD_LENGTH || password.len() > MAX_PASSWORD_LENGTH {
+        return Err(format!("Passwords must be {} to {} characters long.", MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH));
+    }
+
+    Ok(())
+}
+
+fn hash_password(password: &str) -> Result<String, String> {
+    validate_password(password)?;
+
+    let salt: SaltString = SaltString::generate(&mut OsRng);
+    Argon2::default().hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| {
+            error!("Failed to hash a password: {}", e);
+            String::from("The server could not hash your password.")
+        })
+}
+
+fn key(name: &str) -> String {
+    name.to_ascii_lowercase()
+}
@@ -0,0 +1,68 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde_json;
+
+#[derive(Serialize, Debug)]
+pub struct AuditEntry<'a> {
+    // Seconds since the Unix epoch
+    pub timestamp: u64,
+    // Nickname of whoever did it
+    pub actor: &'a str,
+    // e.g. "kick", "ban", "op"
+    pub action: &'a str,
+    pub target: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<&'a str>,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    pub reason: &'a str
+}
+
+// Every moderation action, appended to `<data_dir>/audit.log` as one JSON object per line
+pub struct Audit {
+    path: PathBuf,
+    file: File
+}
+
+impl Audit {
+    pub fn open(data_dir: &Path) -> io::Result<Audit> {
+        let path: PathBuf = data_dir.join("audit.log");
+        let file: File = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        Ok(Audit {
+            path,
+            file
+        })
+    }
+
+    // Makes sure every entry so far is on disk
+    pub fn sync(&self) {
+        if let Err(e) = self.file.sync_all() {
+            error!("Failed to save {}: {}", self.path.display(), e);
+        }
+    }
+
+    pub fn record(&mut self, actor: &str, action: &str, target: &str, channel: Option<&str>, reason: &str) {
+        let entry = AuditEntry {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0),
+            actor,
+            action,
+            target,
+            channel,
+            reason
+        };
+
+        info!("{} {} {}{}{}", actor, action, target,
+              channel.map(|channel| format!(" in {}", channel)).unwrap_or_default(),
+              if reason.is_empty() { String::new() } else { format!(": {}", reason) });
+
+        let result = serde_json::to_string(&entry)
+            .map_err(io::Error::from)
+            .and_then(|line| writeln!(self.file, "{}", line));
+
+        if let Err(e) = result {
+            error!("Failed to write to {}: {}", self.path.display(), e);
+        }
+    }
+}
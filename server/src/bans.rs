@@ -0,0 +1,225 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde_json;
+use nickname;
+
+// Who a ban applies to. Written as "nick:<name>", "account:<name>" or "ip:<address>[/<prefix>]".
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BanTarget {
+    Nickname(String),
+    Account(String),
+    Address(Network)
+}
+
+impl BanTarget {
+    pub fn matches(&self, username: Option<&str>, account: Option<&str>, ip: IpAddr) -> bool {
+        match *self {
+            BanTarget::Nickname(ref name) => username.is_some_and(|username| nickname::same(name, username)),
+            BanTarget::Account(ref name) => account.is_some_and(|account| nickname::same(name, account)),
+            BanTarget::Address(ref network) => network.contains(ip)
+        }
+    }
+
+    // Whether both name the same people. Names are compared ignoring case.
+    pub fn same(&self, other: &BanTarget) -> bool {
+        match (self, other) {
+            (BanTarget::Nickname(a), BanTarget::Nickname(b)) => nickname::same(a, b),
+            (BanTarget::Account(a), BanTarget::Account(b)) => nickname::same(a, b),
+            (BanTarget::Address(a), BanTarget::Address(b)) => a == b,
+            _ => false
+        }
+    }
+}
+
+impl fmt::Display for BanTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BanTarget::Nickname(ref name) => write!(f, "nick:{}", name),
+            BanTarget::Account(ref name) => write!(f, "account:{}", name),
+            BanTarget::Address(ref network) => write!(f, "ip:{}", network)
+        }
+    }
+}
+
+impl FromStr for BanTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<BanTarget, String> {
+        match s.split_once(':') {
+            Some(("nick", name)) if !name.is_empty() => Ok(BanTarget::Nickname(String::from(name))),
+            Some(("account", name)) if !name.is_empty() => Ok(BanTarget::Account(String::from(name))),
+            Some(("ip", network)) => Ok(BanTarget::Address(network.parse()?)),
+            _ => Err(format!("Don't know who {} is. Use nick:<name>, account:<name> or ip:<address>[/<prefix>].", s))
+        }
+    }
+}
+
+// An address range in CIDR notation. A plain address is a range of one.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Network {
+    addr: IpAddr,
+    prefix: u8
+}
+
+impl Network {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask: u32 = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            },
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask: u128 = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            },
+            _ => false
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let max_prefix: u8 = if self.addr.is_ipv4() { 32 } else { 128 };
+        if self.prefix == max_prefix {
+            write!(f, "{}", self.addr)
+        }
+        else {
+            write!(f, "{}/{}", self.addr, self.prefix)
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Network, String> {
+        let invalid = || format!("{} is not an IP address or range.", s);
+
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None)
+        };
+
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let max_prefix: u8 = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix: u8 = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| invalid())?,
+            None => max_prefix
+        };
+
+        if prefix > max_prefix {
+            return Err(invalid());
+        }
+
+        Ok(Network { addr, prefix })
+    }
+}
+
+impl TryFrom<String> for Network {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Network, String> {
+        s.parse()
+    }
+}
+
+impl From<Network> for String {
+    fn from(network: Network) -> String {
+        network.to_string()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Ban {
+    pub target: BanTarget,
+    pub reason: String,
+    // Nickname of whoever issued the ban
+    pub by: String,
+    // Seconds since the Unix epoch
+    pub created: u64
+}
+
+impl fmt::Display for Ban {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.reason.is_empty() {
+            write!(f, "{} (by {})", self.target, self.by)
+        }
+        else {
+            write!(f, "{} (by {}): {}", self.target, self.by, self.reason)
+        }
+    }
+}
+
+// Server-wide bans, kept in `<data_dir>/bans.json`
+pub struct Bans {
+    path: PathBuf,
+    bans: Vec<Ban>
+}
+
+impl Bans {
+    pub fn open(data_dir: &Path) -> io::Result<Bans> {
+        let path: PathBuf = data_dir.join("bans.json");
+
+        let bans: Vec<Ban> = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e)
+        };
+
+        Ok(Bans {
+            path,
+            bans
+        })
+    }
+
+    pub fn all(&self) -> &[Ban] {
+        &self.bans
+    }
+
+    // The first ban that applies to someone with this nickname, account and address
+    pub fn find(&self, username: Option<&str>, account: Option<&str>, ip: IpAddr) -> Option<&Ban> {
+        self.bans.iter().find(|ban| ban.target.matches(username, account, ip))
+    }
+
+    pub fn add(&mut self, ban: Ban) -> Result<(), String> {
+        if self.bans.iter().any(|existing| existing.target.same(&ban.target)) {
+            return Err(format!("{} is already banned.", ban.target));
+        }
+
+        self.bans.push(ban);
+        self.save()
+    }
+
+    pub fn remove(&mut self, target: &BanTarget) -> Result<(), String> {
+        let count: usize = self.bans.len();
+        self.bans.retain(|ban| !ban.target.same(target));
+
+        if self.bans.len() == count {
+            return Err(format!("{} is not banned.", target));
+        }
+
+        self.save()
+    }
+
+    // Same approach as the accounts file: write a copy, then swap it in
+    fn save(&self) -> Result<(), String> {
+        let temp_path: PathBuf = self.path.with_extension("json.tmp");
+
+        let result = serde_json::to_string_pretty(&self.bans)
+            .map_err(io::Error::from)
+            .and_then(|text| fs::write(&temp_path, text))
+            .and_then(|_| fs::rename(&temp_path, &self.path));
+
+        result.map_err(|e| {
+            error!("Failed to save bans to {}: {}", self.path.display(), e);
+            String::from("The server could not save the ban list.")
+        })
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::{BTreeSet, HashMap};
+use std::time::Instant;
+use doosknet::ConnId;
+use roles::Role;
+use nickname;
+
+pub const MAX_CHANNEL_NAME_LENGTH: usize = 24;
+pub const MAX_TOPIC_LENGTH: usize = 160;
+
+// A mute follows the person rather than the connection, so reconnecting doesn't lift it.
+// It covers their nickname, and their account too if they were logged in.
+#[derive(Clone, Debug)]
+pub struct Mute {
+    pub nickname: String,
+    pub account: Option<String>,
+    // None lasts until they are unmuted
+    pub until: Option<Instant>
+}
+
+impl Mute {
+    pub fn matches(&self, nickname: &str, account: Option<&str>) -> bool {
+        nickname::same(&self.nickname, nickname)
+            || self.account.as_ref().is_some_and(|muted| account.is_some_and(|account| nickname::same(muted, account)))
+    }
+
+    pub fn has_expired(&self, now: Instant) -> bool {
+        self.until.is_some_and(|until| now >= until)
+    }
+}
+
+pub struct Channel {
+    // As it was first written. Lookups ignore case.
+    pub name: String,
+    pub topic: String,
+    pub members: BTreeSet<ConnId>,
+    // Members without an entry are plain users
+    pub roles: HashMap<ConnId, Role>,
+    // Who may not talk here. Kept when someone leaves, and the channel is kept while it has any,
+    // so parting and rejoining doesn't lift a mute.
+    pub muted: Vec<Mute>,
+    // Only voiced members and up may talk
+    pub moderated: bool
+}
+
+impl Channel {
+    pub fn new(name: &str) -> Self {
+        Channel {
+            name: String::from(name),
+            topic: String::new(),
+            members: BTreeSet::new(),
+            roles: HashMap::new(),
+            muted: Vec::new(),
+            moderated: false
+        }
+    }
+
+    pub fn is_member(&self, id: ConnId) -> bool {
+        self.members.contains(&id)
+    }
+
+    pub fn role(&self, id: ConnId) -> Role {
+        self.roles.get(&id).cloned().unwrap_or_default()
+    }
+
+    pub fn set_role(&mut self, id: ConnId, role: Role) {
+        if role == Role::User {
+            self.roles.remove(&id);
+        }
+        else {
+            self.roles.insert(id, role);
+        }
+    }
+
+    pub fn is_muted(&self, nickname: &str, account: Option<&str>) -> bool {
+        let now: Instant = Instant::now();
+        self.muted.iter().any(|mute| mute.matches(nickname, account) && !mute.has_expired(now))
+    }
+
+    // Replaces any mute already covering the same person
+    pub fn mute(&mut self, mute: Mute) {
+        self.unmute(&mute.nickname, mute.account.as_deref());
+        self.muted.push(mute);
+    }
+
+    // Returns false if they weren't muted
+    pub fn unmute(&mut self, nickname: &str, account: Option<&str>) -> bool {
+        let now: Instant = Instant::now();
+        let count: usize = self.muted.len();
+        self.muted.retain(|mute| mute.has_expired(now) || !mute.matches(nickname, account));
+
+        let was_muted: bool = self.muted.len() < count;
+        self.prune_mutes(now);
+        was_muted
+    }
+
+    // Nobody is in it and nobody is muted in it, so there is nothing left to keep
+    fn is_abandoned(&self) -> bool {
+        self.members.is_empty() && self.muted.is_empty()
+    }
+
+    // Forgets mutes that have run out
+    pub fn prune_mutes(&mut self, now: Instant) {
+        self.muted.retain(|mute| !mute.has_expired(now));
+    }
+}
+
+// Channels exist while they have members. They are created by the first person to join them.
+pub struct Channels {
+    channels: HashMap<String, Channel>
+}
+
+impl Channels {
+    pub fn new() -> Self {
+        Channels {
+            channels: HashMap::new()
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Channel> {
+        self.channels.get(&key(name))
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Channel> {
+        self.channels.get_mut(&key(name))
+    }
+
+    // Adds `id` to the channel, creating it if needed. Returns false if it was already a member.
+    // Whoever is first in an empty channel becomes its owner.
+    pub fn join(&mut self, name: &str, id: ConnId) -> bool {
+        let channel: &mut Channel = self.channels.entry(key(name)).or_insert_with(|| Channel::new(name));
+        if channel.members.is_empty() {
+            channel.roles.insert(id, Role::Owner);
+        }
+
+        channel.members.insert(id)
+    }
+
+    // Removes `id` from the channel, deleting the channel once it is empty and nobody is muted there.
+    // Returns false if it wasn't a member.
+    pub fn part(&mut self, name: &str, id: ConnId) -> bool {
+        let key: String = key(name);
+        let was_member: bool = match self.channels.get_mut(&key) {
+            Some(channel) => {
+                channel.roles.remove(&id);
+                channel.members.remove(&id)
+            },
+            None => false
+        };
+
+        if self.channels.get(&key).is_some_and(Channel::is_abandoned) {
+            self.channels.remove(&key);
+        }
+
+        was_member
+    }
+
+    // Names of the channels `id` is in, sorted
+    pub fn joined_by(&self, id: ConnId) -> Vec<String> {
+        let mut names: Vec<String> = self.channels.values()
+            .filter(|channel| channel.is_member(id))
+            .map(|channel| channel.name.clone())
+            .collect();
+
+        names.sort_by_key(|name| key(name));
+        names
+    }
+
+    // Everyone who shares at least one channel with `id`, including `id` itself
+    pub fn peers_of(&self, id: ConnId) -> BTreeSet<ConnId> {
+        let mut peers: BTreeSet<ConnId> = BTreeSet::new();
+        peers.insert(id);
+
+        for channel in self.channels.values().filter(|channel| channel.is_member(id)) {
+            peers.extend(channel.members.iter().cloned());
+        }
+
+        peers
+    }
+
+    // The first channel by name, whether they are in it or not, where this person is muted
+    pub fn muting(&self, nickname: &str, account: Option<&str>) -> Option<String> {
+        self.channels.values()
+            .filter(|channel| channel.is_muted(nickname, account))
+            .map(|channel| channel.name.clone())
+            .min_by_key(|name| key(name))
+    }
+
+    // Also deletes empty channels that were only kept for mutes that have now run out
+    pub fn prune_mutes(&mut self, now: Instant) {
+        for channel in self.channels.values_mut() {
+            channel.prune_mutes(now);
+        }
+
+        self.channels.retain(|_, channel| !channel.is_abandoned());
+    }
+
+    // All channels anyone is in, sorted by name
+    pub fn all(&self) -> Vec<&Channel> {
+        let mut channels: Vec<&Channel> = self.channels.values().filter(|channel| !channel.members.is_empty()).collect();
+        channels.sort_by_key(|channel| key(&channel.name));
+        channels
+    }
+}
+
+impl Default for Channels {
+    fn default() -> Self {
+        Channels::new()
+    }
+}
+
+fn key(name: &str) -> String {
+    name.to_ascii_lowercase()
+}
+
+// Channel names look like "#rust": a '#' followed by letters, digits, '_' or '-'
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if !name.starts_with('#') {
+        return Err(format!("Channel names start with '#', like #{}.", name));
+    }
+
+    if name.len() < 2 || name.len() > MAX_CHANNEL_NAME_LENGTH {
+        return Err(format!("Channel names must be 2 to {} characters long.", MAX_CHANNEL_NAME_LENGTH));
+    }
+
+    if !name[1..].chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(String::from("Channel names may only contain letters, digits, '_' and '-'."));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,823 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use doosknet::*;
+use config::Config;
+use commands::{self, Caller, Commands};
+use channels::{self, Channel, Channels};
+use history::{Entry, History};
+use accounts::Accounts;
+use bans::{Ban, Bans};
+use audit::Audit;
+use roles::Role;
+use flood::{FloodControl, Verdict};
+use nickname;
+use logger;
+
+// Wrong passwords allowed during the handshake before the connection is dropped
+const MAX_FAILED_LOGINS: u32 = 3;
+
+pub struct User {
+    pub username: Option<String>,
+    pub ip: IpAddr,
+    // The account this connection has logged in to
+    pub account: Option<String>,
+    // Capabilities agreed on in the Hello, kept until the Welcome is sent
+    pub capabilities: Vec<String>,
+    pub has_said_hello: bool,
+    pub failed_logins: u32,
+    pub connected_at: Instant,
+    // When the connection last sent a packet. Heartbeats don't count.
+    pub last_active: Instant
+}
+
+impl User {
+    pub fn new(ip: IpAddr) -> Self {
+        User {
+            username: None,
+            ip,
+            account: None,
+            capabilities: Vec::new(),
+            has_said_hello: false,
+            failed_logins: 0,
+            connected_at: Instant::now(),
+            last_active: Instant::now()
+        }
+    }
+
+    // A connection only takes part in chat once it has completed the handshake
+    pub fn is_joined(&self) -> bool {
+        self.username.is_some()
+    }
+}
+
+// Everything the server knows about the people connected to it and the channels they are in
+pub struct ChatServer {
+    pub server: Server,
+    pub config: Config,
+    pub users: HashMap<ConnId, User>,
+    pub channels: Channels,
+    pub history: History,
+    pub accounts: Accounts,
+    pub bans: Bans,
+    pub audit: Audit,
+    pub flood: FloodControl,
+    pub commands: Commands
+}
+
+impl ChatServer {
+    pub fn new(server: Server, config: Config, history: History, accounts: Accounts, bans: Bans, audit: Audit) -> Self {
+        ChatServer {
+            server,
+            flood: FloodControl::new(config.flood.clone()),
+            config,
+            users: HashMap::new(),
+            channels: Channels::new(),
+            history,
+            accounts,
+            bans,
+            audit,
+            commands: commands::builtin()
+        }
+    }
+
+    pub fn handle_event(&mut self, event: NetworkEvent) {
+        match event {
+            NetworkEvent::Connected(id) => self.on_connected(id),
+            NetworkEvent::Disconnected(id) => {
+                info!(conn:% = id; "Disconnected");
+
+                self.remove_user(id);
+            },
+            NetworkEvent::TimedOut(id) => {
+                info!(conn:% = id; "Stopped responding");
+
+                // Let its channels know why it is leaving; the Disconnected that follows has nothing left to do
+                if let Some(username) = self.nickname(id) {
+                    for name in self.channels.joined_by(id) {
+                        self.send_channel(&name, &Message::notice(&format!("{} timed out.", username)));
+                    }
+                }
+
+                self.remove_user(id);
+            },
+            NetworkEvent::DecodeFailed(id, DecodeError::UnsupportedVersion(version)) => {
+                self.reject(id, &version_mismatch(version));
+            },
+            NetworkEvent::DecodeFailed(id, e) => {
+                if e.is_recoverable() {
+                    warn!(conn:% = id; "Dropping a packet: {}", e);
+                }
+                else {
+                    self.reject(id, &e.to_string());
+                }
+            },
+            NetworkEvent::Message(id, packet, bytes) => {
+                let is_joined: bool = match self.users.get_mut(&id) {
+                    Some(user) => {
+                        user.last_active = Instant::now();
+                        user.is_joined()
+                    },
+                    None => return
+                };
+
+                if !self.check_flood(id, bytes) {
+                    return;
+                }
+
+                if is_joined {
+                    self.handle_packet(id, packet);
+                }
+                else {
+                    self.handle_handshake(id, packet);
+                }
+            }
+        }
+    }
+
+    // Drops connections that are taking too long to pick a nickname, or have gone idle.
+    // Called regularly from the main loop.
+    pub fn tick(&mut self) {
+        let now: Instant = Instant::now();
+        let handshake_timeout: Duration = Duration::from_secs(self.config.handshake_timeout_seconds);
+        let idle_timeout: Option<Duration> = match self.config.idle_timeout_seconds {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds))
+        };
+
+        let mut expired: Vec<(ConnId, &str)> = Vec::new();
+        for (id, user) in &self.users {
+            if !user.is_joined() && now.saturating_duration_since(user.connected_at) >= handshake_timeout {
+                expired.push((*id, "You took too long to pick a nickname."));
+            }
+            else if user.is_joined() && idle_timeout.is_some_and(|timeout| now.saturating_duration_since(user.last_active) >= timeout) {
+                expired.push((*id, "Disconnected for being idle."));
+            }
+        }
+
+        for (id, reason) in expired {
+            self.reject(id, reason);
+        }
+
+        self.channels.prune_mutes(now);
+    }
+
+    fn on_connected(&mut self, id: ConnId) {
+        let peer: SocketAddr = self.server.peer_addr(id).unwrap_or_else(|| SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0));
+        let ip: IpAddr = peer.ip();
+        info!(conn:% = id, peer:% = peer; "New connection");
+
+        let connections_from_ip: usize = self.users.values().filter(|user| user.ip == ip).count();
+
+        // The client announces itself with a Hello before it joins the room
+        self.users.insert(id, User::new(ip));
+        self.flood.add(id, ip);
+
+        if self.users.len() > self.config.max_connections {
+            self.reject(id, "The server is full.");
+            return;
+        }
+
+        if self.config.max_connections_per_ip != 0 && connections_from_ip >= self.config.max_connections_per_ip {
+            self.reject(id, "Too many connections from your address.");
+            return;
+        }
+
+        if let Some(reason) = self.ban_reason(id, None, None) {
+            self.reject(id, &reason);
+            return;
+        }
+
+        info!("There are now {} clients connected.", self.users.len());
+    }
+
+    // Counts a packet of `bytes` on the wire against the sender's rate limits. Returns false if it should be dropped.
+    fn check_flood(&mut self, id: ConnId, bytes: usize) -> bool {
+        match self.flood.check(id, bytes) {
+            Verdict::Allow => true,
+            Verdict::Throttle => false,
+            Verdict::Warn => {
+                warn!(conn:% = id; "Sending too fast");
+                self.send(id, &Message::notice("You are sending too fast. Slow down, or you will be muted."));
+                false
+            },
+            Verdict::Mute(duration) => {
+                warn!(conn:% = id; "Muting for {} seconds for flooding", duration.as_secs());
+                self.send(id, &Message::notice(&format!("You have been muted for {} seconds for flooding.", duration.as_secs())));
+                false
+            },
+            Verdict::Disconnect => {
+                warn!(conn:% = id; "Disconnecting for flooding");
+                self.reject(id, "Disconnected for flooding.");
+                false
+            }
+        }
+    }
+
+    fn handle_handshake(&mut self, id: ConnId, packet: Message) {
+        let has_said_hello: bool = self.users.get(&id).is_some_and(|user| user.has_said_hello);
+
+        match packet {
+            Message::Hello { version, capabilities, username } => {
+                if version != PROTOCOL_VERSION {
+                    self.reject(id, &version_mismatch(version));
+                    return;
+                }
+
+                if let Some(user) = self.users.get_mut(&id) {
+                    user.capabilities = negotiate_capabilities(&capabilities);
+                    user.has_said_hello = true;
+                }
+
+                self.request_nickname(id, &username);
+            },
+            Message::Login { username, password } if has_said_hello => self.login(id, &username, &password),
+            _ => self.reject(id, "Expected a Hello packet before any other traffic.")
+        }
+    }
+
+    // Welcomes the connection under `username`, unless it is taken or needs a password
+    fn request_nickname(&mut self, id: ConnId, username: &str) {
+        if let Err(reason) = self.check_nickname(username, None) {
+            info!(conn:% = id; "Can't use the nickname {}: {}", username, reason);
+            self.send(id, &Message::nickname_rejected(username, &reason));
+            return;
+        }
+
+        if self.accounts.exists(username) {
+            self.send(id, &Message::auth_required(username, ""));
+            return;
+        }
+
+        self.welcome(id, username, None);
+    }
+
+    fn login(&mut self, id: ConnId, username: &str, password: &str) {
+        if let Err(reason) = self.check_nickname(username, None) {
+            self.send(id, &Message::nickname_rejected(username, &reason));
+            return;
+        }
+
+        let account: String = match self.accounts.get(username) {
+            Some(account) => account.name.clone(),
+            None => {
+                self.send(id, &Message::nickname_rejected(username, &format!("There is no account called {}.", username)));
+                return;
+            }
+        };
+
+        if !self.accounts.verify(&account, password) {
+            info!(conn:% = id; "Failed to log in as {}", account);
+
+            if self.count_failed_login(id) {
+                self.send(id, &Message::auth_required(&account, "Wrong password."));
+            }
+
+            return;
+        }
+
+        self.welcome(id, &account, Some(account.clone()));
+    }
+
+    fn welcome(&mut self, id: ConnId, username: &str, account: Option<String>) {
+        if let Some(reason) = self.ban_reason(id, Some(username), account.as_deref()) {
+            self.reject(id, &reason);
+            return;
+        }
+
+        info!(conn:% = id; "Joined as {}{}", username, if account.is_some() { " (logged in)" } else { "" });
+
+        let capabilities: Vec<String> = match self.users.get_mut(&id) {
+            Some(user) => {
+                user.username = Some(String::from(username));
+                user.account = account;
+                user.capabilities.clone()
+            },
+            None => return
+        };
+
+        let welcome = Message::welcome(username, capabilities, &self.config.server_name, &self.config.motd);
+        self.send(id, &welcome);
+
+        if self.can_speak(id) {
+            self.join_default_channel(id);
+        }
+        else {
+            self.send(id, &Message::notice("This server only lets registered users talk. Use /register <password> to create an account for your nickname, or /login <nickname> <password>."));
+        }
+    }
+
+    pub fn join_default_channel(&mut self, id: ConnId) {
+        let default_channel: String = self.config.default_channel.clone();
+        if default_channel.is_empty() {
+            return;
+        }
+
+        if let Err(reason) = self.join_channel(id, &default_channel) {
+            self.send(id, &Message::error(&reason));
+        }
+    }
+
+    // Without require_auth everyone may talk, otherwise only those who have logged in
+    pub fn can_speak(&self, id: ConnId) -> bool {
+        !self.config.require_auth || self.users.get(&id).is_some_and(|user| user.account.is_some())
+    }
+
+    fn check_can_speak(&self, id: ConnId) -> Result<(), String> {
+        if self.can_speak(id) {
+            Ok(())
+        }
+        else {
+            Err(String::from("You need to /register or /login before you can do that."))
+        }
+    }
+
+    // Whether `id` may send lines to `channel`: not muted there, and voiced if the channel is moderated
+    pub fn check_can_talk(&self, id: ConnId, channel: &str) -> Result<(), String> {
+        self.check_flood_mute(id)?;
+
+        let found = match self.channels.get(channel) {
+            Some(found) => found,
+            None => return Ok(())
+        };
+
+        if self.is_muted_in(id, found) {
+            return Err(format!("You are muted in {}.", found.name));
+        }
+
+        if found.moderated && self.channel_role(id, channel) < Role::Voice {
+            return Err(format!("{} is moderated. Only voiced members can talk.", found.name));
+        }
+
+        Ok(())
+    }
+
+    fn is_muted_in(&self, id: ConnId, channel: &Channel) -> bool {
+        let user: Option<&User> = self.users.get(&id);
+        let nickname: &str = user.and_then(|user| user.username.as_deref()).unwrap_or("");
+        let account: Option<&str> = user.and_then(|user| user.account.as_deref());
+        channel.is_muted(nickname, account)
+    }
+
+    fn check_flood_mute(&self, id: ConnId) -> Result<(), String> {
+        match self.flood.muted_for(id) {
+            Some(remaining) => Err(format!("You are muted for flooding for another {} seconds.", remaining.as_secs() + 1)),
+            None => Ok(())
+        }
+    }
+
+    // The role an account gives a connection everywhere. Connections that aren't logged in are plain users.
+    pub fn server_role(&self, id: ConnId) -> Role {
+        let account: &str = match self.users.get(&id).and_then(|user| user.account.as_ref()) {
+            Some(account) => account,
+            None => return Role::User
+        };
+
+        if self.config.owners.iter().any(|owner| nickname::same(owner, account)) {
+            return Role::Owner;
+        }
+
+        self.accounts.get(account).map(|account| account.role).unwrap_or_default()
+    }
+
+    // The higher of the connection's server role and its role in `channel`
+    pub fn channel_role(&self, id: ConnId, channel: &str) -> Role {
+        let channel_role: Role = self.channels.get(channel).map(|channel| channel.role(id)).unwrap_or_default();
+        self.server_role(id).max(channel_role)
+    }
+
+    fn handle_packet(&mut self, id: ConnId, packet: Message) {
+        match packet {
+            Message::Chat { channel, message, .. } => {
+                // Lines starting with '/' are commands. "//" sends a line that starts with a literal '/'.
+                if message.starts_with("//") {
+                    self.say(id, &channel, &message[1..]);
+                }
+                else if let Some(line) = message.strip_prefix('/') {
+                    commands::dispatch(self, &Caller { id, channel }, line);
+                }
+                else {
+                    self.say(id, &channel, &message);
+                }
+            },
+            Message::DirectMessage { target, message, .. } => {
+                if let Err(reason) = self.direct_message(id, &target, &message) {
+                    self.send(id, &Message::error(&reason));
+                }
+            },
+            Message::HistoryRequest { channel, before, count } => {
+                match self.member_channel(id, &channel) {
+                    Ok(channel) => self.send_history(id, &channel, before, count as usize),
+                    Err(reason) => self.send(id, &Message::error(&reason))
+                }
+            },
+            _ => {
+                debug!(conn:% = id; "Ignoring unexpected packet {:?}", packet);
+            }
+        }
+    }
+
+    // Relays a chat line to everyone in the channel
+    fn say(&mut self, id: ConnId, channel: &str, message: &str) {
+        let channel: String = match self.check_can_speak(id)
+            .and_then(|_| self.member_channel(id, channel))
+            .and_then(|channel| self.check_can_talk(id, &channel).map(|_| channel)) {
+            Ok(channel) => channel,
+            Err(reason) => {
+                self.send(id, &Message::error(&reason));
+                return;
+            }
+        };
+
+        // Never trust the sender the client wrote; use the nickname it joined with
+        let sender: String = self.nickname(id).unwrap_or_default();
+        info!(target: "chat", conn:% = id; "{} {}> {}", channel, sender, message);
+
+        let packet = Message::chat(&channel, &sender, message);
+        if let Err(reason) = self.publish(&channel, &packet) {
+            self.send(id, &Message::error(&reason));
+        }
+    }
+
+    // Stores a Chat or Action in the channel's history and sends it to the channel.
+    // Only what can actually be sent is stored, so history never replays a message nobody saw.
+    pub fn publish(&mut self, channel: &str, packet: &Message) -> Result<(), String> {
+        if let Err(e) = serialize_packet(packet) {
+            warn!("Not sending {:?}: {}", packet, e);
+            return Err(String::from("That message is too long to send."));
+        }
+
+        match *packet {
+            Message::Chat { ref sender, ref message, .. } => self.history.append(channel, sender, message, false),
+            Message::Action { ref sender, ref message, .. } => self.history.append(channel, sender, message, true),
+            _ => ()
+        }
+
+        self.send_channel(channel, packet);
+
+        Ok(())
+    }
+
+    // Sends stored messages of a channel, oldest first, followed by a HistoryEnd
+    pub fn send_history(&mut self, id: ConnId, channel: &str, before: u64, count: usize) {
+        let (entries, has_more) = self.history.page(channel, before, count);
+
+        for entry in entries {
+            let packet: Message = history_packet(channel, entry);
+            self.send(id, &packet);
+        }
+
+        self.send(id, &Message::HistoryEnd { channel: String::from(channel), has_more });
+    }
+
+    // Delivers a private message to whoever is using the `target` nickname, and echoes it to the sender
+    pub fn direct_message(&mut self, id: ConnId, target: &str, message: &str) -> Result<(), String> {
+        self.check_can_speak(id)?;
+        self.check_flood_mute(id)?;
+
+        let target_id: ConnId = self.find_user(target)
+            .ok_or_else(|| format!("{} is not online.", target))?;
+
+        let sender: String = self.nickname(id).unwrap_or_default();
+        let target: String = self.nickname(target_id).unwrap_or_default();
+        info!(target: "chat", conn:% = id; "{} -> {}: {}", sender, target, message);
+
+        let packet = Message::direct_message(&sender, &target, message);
+        self.send(target_id, &packet);
+        if target_id != id {
+            self.send(id, &packet);
+        }
+
+        Ok(())
+    }
+
+    // The proper name of `channel`, as long as `id` is in it
+    pub fn member_channel(&self, id: ConnId, channel: &str) -> Result<String, String> {
+        match self.channels.get(channel) {
+            Some(found) if found.is_member(id) => Ok(found.name.clone()),
+            _ if channel.is_empty() => Err(String::from("You are not in a channel. Use /join #channel to join one.")),
+            _ => Err(format!("You are not in {}.", channel))
+        }
+    }
+
+    pub fn join_channel(&mut self, id: ConnId, name: &str) -> Result<(), String> {
+        self.check_can_speak(id)?;
+        channels::validate_name(name)?;
+
+        let username: String = self.nickname(id).unwrap_or_default();
+        let is_new_member: bool = self.channels.join(name, id);
+
+        // Being first back into a channel kept for its mutes doesn't make someone muted there its owner
+        let is_muted: bool = self.channels.get(name).is_some_and(|found| self.is_muted_in(id, found));
+        if is_new_member && is_muted {
+            if let Some(found) = self.channels.get_mut(name) {
+                found.set_role(id, Role::User);
+            }
+        }
+
+        let (name, topic) = match self.channels.get(name) {
+            Some(channel) => (channel.name.clone(), channel.topic.clone()),
+            None => return Ok(())
+        };
+
+        if is_new_member {
+            info!("{} joined {}", username, name);
+            self.send_channel(&name, &Message::Join { channel: name.clone(), username });
+        }
+
+        self.send(id, &Message::Joined { channel: name.clone(), topic });
+
+        // Catch them up on what was said before they arrived
+        let replay: usize = self.config.history_replay;
+        if is_new_member && replay > 0 {
+            self.send_history(id, &name, 0, replay);
+        }
+
+        Ok(())
+    }
+
+    pub fn part_channel(&mut self, id: ConnId, name: &str) -> Result<(), String> {
+        let name: String = self.member_channel(id, name)?;
+        let username: String = self.nickname(id).unwrap_or_default();
+        info!("{} left {}", username, name);
+
+        // They get to see themselves leave, so tell them before they are gone
+        self.send_channel(&name, &Message::Leave { channel: name.clone(), username });
+        self.channels.part(&name, id);
+
+        Ok(())
+    }
+
+    // Counts a wrong password against the connection, during the handshake or from /login.
+    // Returns false if that was one too many, in which case the connection is already being dropped.
+    pub fn count_failed_login(&mut self, id: ConnId) -> bool {
+        let failed_logins: u32 = match self.users.get_mut(&id) {
+            Some(user) => {
+                user.failed_logins += 1;
+                user.failed_logins
+            },
+            None => return false
+        };
+
+        if failed_logins >= MAX_FAILED_LOGINS {
+            self.reject(id, "Too many wrong passwords.");
+            return false;
+        }
+
+        true
+    }
+
+    // Changes the nickname of a connection and tells everyone who can see it
+    pub fn rename(&mut self, id: ConnId, new_username: &str) -> Result<(), String> {
+        let old_username: String = self.nickname(id).unwrap_or_default();
+        if old_username == new_username {
+            return Err(format!("You are already called {}.", new_username));
+        }
+
+        self.check_nickname(new_username, Some(id))?;
+
+        // Mutes go by nickname, so a new one would shake them off
+        let account: Option<String> = self.users.get(&id).and_then(|user| user.account.clone());
+        if let Some(channel) = self.channels.muting(&old_username, account.as_deref()) {
+            return Err(format!("You can't change your nickname while you are muted in {}.", channel));
+        }
+
+        if let Some(ban) = self.find_ban(id, Some(new_username), None) {
+            return Err(format!("The nickname {} is banned: {}", new_username, ban.reason));
+        }
+
+        let is_own_account: bool = account.as_ref().is_some_and(|account| nickname::same(account, new_username));
+        if self.accounts.exists(new_username) && !is_own_account {
+            return Err(format!("{} is registered. Use /login {} <password> if it is yours.", new_username, new_username));
+        }
+
+        info!("{} is now known as {}", old_username, new_username);
+
+        if let Some(user) = self.users.get_mut(&id) {
+            user.username = Some(String::from(new_username));
+        }
+
+        self.send_peers(id, &Message::NickChange { old_username, new_username: String::from(new_username) });
+
+        Ok(())
+    }
+
+    // Forgets a connection, telling each of its channels that it left
+    fn remove_user(&mut self, id: ConnId) {
+        self.flood.remove(id);
+
+        let username: String = match self.users.remove(&id) {
+            Some(User { username: Some(username), .. }) => username,
+            _ => return
+        };
+
+        for name in self.channels.joined_by(id) {
+            self.channels.part(&name, id);
+            self.send_channel(&name, &Message::Leave { channel: name.clone(), username: username.clone() });
+        }
+    }
+
+    // Stops taking new connections, tells everyone why, and closes each connection once what is queued for it
+    // has been sent. Keep polling the server until it has no connections left.
+    pub fn shut_down(&mut self, reason: &str) {
+        info!("Shutting down: {}", reason);
+
+        if let Err(e) = self.server.stop_listening() {
+            warn!("Failed to stop listening: {}", e);
+        }
+
+        let ids: Vec<ConnId> = self.server.connection_ids();
+        self.multicast(&ids, &Message::notice(reason));
+        for id in ids {
+            self.server.close(id);
+        }
+
+        // Accounts and bans are saved as they change; make sure the logs have hit the disk too
+        self.history.sync();
+        self.audit.sync();
+    }
+
+    // Switches to new settings. Returns the names of those that only change when the server restarts,
+    // which keep their old values until then.
+    pub fn reload(&mut self, mut config: Config) -> Vec<&'static str> {
+        let mut needs_restart: Vec<&'static str> = Vec::new();
+
+        if config.bind != self.config.bind {
+            needs_restart.push("bind");
+            config.bind = self.config.bind.clone();
+        }
+
+        if config.tls != self.config.tls {
+            needs_restart.push("tls");
+            config.tls = self.config.tls.clone();
+        }
+
+        if config.data_dir != self.config.data_dir {
+            needs_restart.push("data_dir");
+            config.data_dir = self.config.data_dir.clone();
+        }
+
+        if config.log_file != self.config.log_file {
+            needs_restart.push("log_file");
+            config.log_file = self.config.log_file.clone();
+        }
+
+        if config.console != self.config.console {
+            needs_restart.push("console");
+            config.console = self.config.console;
+        }
+
+        self.server.set_event_capacity(config.event_capacity);
+        self.server.set_heartbeat(config.heartbeat());
+        self.flood.set_config(config.flood.clone());
+        if let Err(e) = logger::set_level(&config.log_level) {
+            warn!("Failed to change the log level: {}", e);
+        }
+
+        self.config = config;
+        info!("Reloaded the configuration");
+
+        needs_restart
+    }
+
+    // Counters for monitoring, one per line
+    pub fn stats(&self) -> Vec<String> {
+        let stats = self.flood.stats;
+
+        vec![
+            format!("Connections: {} ({} joined)", self.users.len(), self.users.values().filter(|user| user.is_joined()).count()),
+            format!("Channels: {}", self.channels.all().len()),
+            format!("Received: {} packets, {} bytes", stats.packets, stats.bytes),
+            format!("Flood control: {} packets dropped, {} warnings, {} mutes, {} disconnects", stats.dropped, stats.warnings, stats.mutes, stats.disconnects)
+        ]
+    }
+
+    pub fn nickname(&self, id: ConnId) -> Option<String> {
+        self.users.get(&id).and_then(|user| user.username.clone())
+    }
+
+    // The connection currently using `nickname`, ignoring case
+    pub fn find_user(&self, nickname: &str) -> Option<ConnId> {
+        self.users.iter()
+            .find(|&(_, user)| user.username.as_ref().is_some_and(|username| nickname::same(username, nickname)))
+            .map(|(id, _)| *id)
+    }
+
+    // Whether `username` may be used. `owner` is allowed to keep (or re-case) its own nickname.
+    pub fn check_nickname(&self, username: &str, owner: Option<ConnId>) -> Result<(), String> {
+        nickname::validate(username)?;
+
+        match self.find_user(username) {
+            Some(id) if Some(id) != owner => Err(format!("The nickname {} is already taken.", username)),
+            _ => Ok(())
+        }
+    }
+
+    // The first ban that applies to the connection, checking its address plus the given nickname and account
+    pub fn find_ban(&self, id: ConnId, username: Option<&str>, account: Option<&str>) -> Option<&Ban> {
+        let ip: IpAddr = self.users.get(&id)?.ip;
+        self.bans.find(username, account, ip)
+    }
+
+    fn ban_reason(&self, id: ConnId, username: Option<&str>, account: Option<&str>) -> Option<String> {
+        self.find_ban(id, username, account).map(banned_message)
+    }
+
+    // Drops everyone who is connected but covered by a ban, e.g. right after one was added
+    pub fn enforce_bans(&mut self) {
+        let banned: Vec<(ConnId, String)> = self.users.iter()
+            .filter_map(|(id, user)| {
+                self.bans.find(user.username.as_deref(), user.account.as_deref(), user.ip)
+                    .map(|ban| (*id, banned_message(ban)))
+            })
+            .collect();
+
+        for (id, reason) in banned {
+            self.reject(id, &reason);
+        }
+    }
+
+    // Tells the client why, then drops it once the message has been sent
+    pub fn reject(&mut self, id: ConnId, reason: &str) {
+        info!(conn:% = id; "Rejecting: {}", reason);
+
+        self.send(id, &Message::rejected(reason));
+        self.server.close(id);
+
+        // Rejected clients are no longer part of any channel
+        self.remove_user(id);
+    }
+
+    pub fn send(&mut self, id: ConnId, packet: &Message) {
+        if let Err(e) = self.server.send(id, packet) {
+            warn!("Dropping outgoing packet {:?}: {}", packet, e);
+        }
+    }
+
+    pub fn send_channel(&mut self, channel: &str, packet: &Message) {
+        let ids: Vec<ConnId> = match self.channels.get(channel) {
+            Some(channel) => channel.members.iter().cloned().collect(),
+            None => return
+        };
+
+        self.multicast(&ids, packet);
+    }
+
+    // Sends to every connection that has joined, whatever channels it is in
+    pub fn send_everyone(&mut self, packet: &Message) {
+        let ids: Vec<ConnId> = self.users.iter()
+            .filter(|&(_, user)| user.is_joined())
+            .map(|(id, _)| *id)
+            .collect();
+
+        self.multicast(&ids, packet);
+    }
+
+    // Sends to `id` and everyone who shares a channel with it
+    pub fn send_peers(&mut self, id: ConnId, packet: &Message) {
+        let ids: Vec<ConnId> = self.channels.peers_of(id).into_iter().collect();
+        self.multicast(&ids, packet);
+    }
+
+    fn multicast(&mut self, ids: &[ConnId], packet: &Message) {
+        if let Err(e) = self.server.multicast(ids, packet) {
+            warn!("Dropping outgoing packet {:?}: {}", packet, e);
+        }
+    }
+}
+
+// A stored message as a packet. Messages that only just fit in a Chat packet are shortened to fit the extra fields.
+fn history_packet(channel: &str, entry: Entry) -> Message {
+    let mut message: String = entry.message;
+
+    loop {
+        let packet = Message::HistoryEntry {
+            channel: String::from(channel),
+            id: entry.id,
+            timestamp: entry.timestamp,
+            sender: entry.sender.clone(),
+            message: message.clone(),
+            is_action: entry.is_action
+        };
+
+        match serialize_packet(&packet) {
+            Err(Error::PacketTooLarge(_)) if !message.is_empty() => {
+                message.pop();
+            },
+            _ => return packet
+        }
+    }
+}
+
+fn banned_message(ban: &Ban) -> String {
+    if ban.reason.is_empty() {
+        String::from("You are banned from this server.")
+    }
+    else {
+        format!("You are banned from this server: {}", ban.reason)
+    }
+}
+
+fn version_mismatch(version: u16) -> String {
+    format!("Incompatible protocol version {} (server speaks version {}). Please update your client.", version, PROTOCOL_VERSION)
+}
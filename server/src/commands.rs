@@ -0,0 +1,949 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use doosknet::*;
+use chat::ChatServer;
+use channels::{Mute, MAX_TOPIC_LENGTH};
+use roles::Role;
+use bans::{Ban, BanTarget};
+
+// Longest line we put in a single reply, leaving room for the packet framing
+const MAX_REPLY_LENGTH: usize = 200;
+
+// Longest /mute, one week
+const MAX_MUTE_MINUTES: u64 = 7 * 24 * 60;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Permission {
+    Everyone,
+    // At least this role in the channel the command was typed in. Server roles count too.
+    Channel(Role),
+    // At least this role on the whole server
+    Server(Role)
+}
+
+// Who ran a command, and from which channel
+pub struct Caller {
+    pub id: ConnId,
+    pub channel: String
+}
+
+// Runs a command for the caller. An Err is sent back to the caller's connection as an error message.
+pub type Handler = fn(&mut ChatServer, &Caller, &[&str]) -> Result<(), String>;
+
+#[derive(Clone, Copy)]
+pub struct Command {
+    pub name: &'static str,
+    // Arguments as shown by /help, e.g. "<nickname>"
+    pub usage: &'static str,
+    pub description: &'static str,
+    pub permission: Permission,
+    // How many arguments the command takes. The last one gets the rest of the line, spaces included.
+    pub min_args: usize,
+    pub max_args: usize,
+    pub handler: Handler
+}
+
+impl Command {
+    pub fn usage_line(&self) -> String {
+        if self.usage.is_empty() {
+            format!("/{}", self.name)
+        }
+        else {
+            format!("/{} {}", self.name, self.usage)
+        }
+    }
+}
+
+pub struct Commands {
+    commands: BTreeMap<&'static str, Command>
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Commands {
+            commands: BTreeMap::new()
+        }
+    }
+
+    // Adds a command, replacing any existing command with the same name
+    pub fn register(&mut self, command: Command) {
+        self.commands.insert(command.name, command);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Command> {
+        self.commands.get(name).cloned()
+    }
+
+    // All commands, sorted by name
+    pub fn all(&self) -> Vec<Command> {
+        self.commands.values().cloned().collect()
+    }
+}
+
+impl Default for Commands {
+    fn default() -> Self {
+        Commands::new()
+    }
+}
+
+pub fn builtin() -> Commands {
+    let mut commands: Commands = Commands::new();
+
+    commands.register(Command {
+        name: "help",
+        usage: "[command]",
+        description: "Lists commands, or explains one",
+        permission: Permission::Everyone,
+        min_args: 0,
+        max_args: 1,
+        handler: help
+    });
+
+    commands.register(Command {
+        name: "nick",
+        usage: "<nickname>",
+        description: "Changes your nickname",
+        permission: Permission::Everyone,
+        min_args: 1,
+        max_args: 1,
+        handler: nick
+    });
+
+    commands.register(Command {
+        name: "register",
+        usage: "<password>",
+        description: "Registers your nickname so only you can use it",
+        permission: Permission::Everyone,
+        min_args: 1,
+        max_args: 1,
+        handler: register
+    });
+
+    commands.register(Command {
+        name: "login",
+        usage: "<nickname> <password>",
+        description: "Logs in to a registered nickname",
+        permission: Permission::Everyone,
+        min_args: 2,
+        max_args: 2,
+        handler: login
+    });
+
+    commands.register(Command {
+        name: "passwd",
+        usage: "<old password> <new password>",
+        description: "Changes your password",
+        permission: Permission::Everyone,
+        min_args: 2,
+        max_args: 2,
+        handler: passwd
+    });
+
+    commands.register(Command {
+        name: "me",
+        usage: "<action>",
+        description: "Describes what you are doing",
+        permission: Permission::Everyone,
+        min_args: 1,
+        max_args: 1,
+        handler: me
+    });
+
+    commands.register(Command {
+        name: "msg",
+        usage: "<nickname> <message>",
+        description: "Sends a private message",
+        permission: Permission::Everyone,
+        min_args: 2,
+        max_args: 2,
+        handler: msg
+    });
+
+    commands.register(Command {
+        name: "who",
+        usage: "[#channel]",
+        description: "Lists who is in this channel, or another one",
+        permission: Permission::Everyone,
+        min_args: 0,
+        max_args: 1,
+        handler: who
+    });
+
+    commands.register(Command {
+        name: "join",
+        usage: "<#channel>",
+        description: "Joins a channel, creating it if nobody is in it yet",
+        permission: Permission::Everyone,
+        min_args: 1,
+        max_args: 1,
+        handler: join
+    });
+
+    commands.register(Command {
+        name: "part",
+        usage: "[#channel]",
+        description: "Leaves this channel, or another one",
+        permission: Permission::Everyone,
+        min_args: 0,
+        max_args: 1,
+        handler: part
+    });
+
+    commands.register(Command {
+        name: "list",
+        usage: "",
+        description: "Lists channels",
+        permission: Permission::Everyone,
+        min_args: 0,
+        max_args: 0,
+        handler: list
+    });
+
+    commands.register(Command {
+        name: "topic",
+        usage: "[topic]",
+        description: "Shows the channel topic. Operators can change it.",
+        permission: Permission::Everyone,
+        min_args: 0,
+        max_args: 1,
+        handler: topic
+    });
+
+    commands.register(Command {
+        name: "motd",
+        usage: "",
+        description: "Shows the message of the day",
+        permission: Permission::Everyone,
+        min_args: 0,
+        max_args: 0,
+        handler: motd
+    });
+
+    commands.register(Command {
+        name: "announce",
+        usage: "<message>",
+        description: "Sends a notice to everyone in the channel",
+        permission: Permission::Channel(Role::Op),
+        min_args: 1,
+        max_args: 1,
+        handler: announce
+    });
+
+    commands.register(Command {
+        name: "kick",
+        usage: "<nickname> [reason]",
+        description: "Removes someone from the channel",
+        permission: Permission::Channel(Role::Op),
+        min_args: 1,
+        max_args: 2,
+        handler: kick
+    });
+
+    commands.register(Command {
+        name: "mute",
+        usage: "<nickname> [minutes]",
+        description: "Stops someone from talking in the channel, for a while or until unmuted",
+        permission: Permission::Channel(Role::Op),
+        min_args: 1,
+        max_args: 2,
+        handler: mute
+    });
+
+    commands.register(Command {
+        name: "unmute",
+        usage: "<nickname>",
+        description: "Lets someone muted in the channel talk again",
+        permission: Permission::Channel(Role::Op),
+        min_args: 1,
+        max_args: 1,
+        handler: unmute
+    });
+
+    commands.register(Command {
+        name: "op",
+        usage: "<nickname>",
+        description: "Makes a member an operator of the channel",
+        permission: Permission::Channel(Role::Owner),
+        min_args: 1,
+        max_args: 1,
+        handler: op
+    });
+
+    commands.register(Command {
+        name: "voice",
+        usage: "<nickname>",
+        description: "Lets a member talk while the channel is moderated",
+        permission: Permission::Channel(Role::Op),
+        min_args: 1,
+        max_args: 1,
+        handler: voice
+    });
+
+    commands.register(Command {
+        name: "deop",
+        usage: "<nickname>",
+        description: "Takes away a member's channel role",
+        permission: Permission::Channel(Role::Op),
+        min_args: 1,
+        max_args: 1,
+        handler: deop
+    });
+
+    commands.register(Command {
+        name: "moderate",
+        usage: "<on|off>",
+        description: "Turns moderated mode on or off. Only voiced members can talk in moderated channels.",
+        permission: Permission::Channel(Role::Op),
+        min_args: 1,
+        max_args: 1,
+        handler: moderate
+    });
+
+    commands.register(Command {
+        name: "ban",
+        usage: "<nickname | nick:<name> | account:<name> | ip:<address>[/<prefix>]> [reason]",
+        description: "Bans someone from the server and disconnects them",
+        permission: Permission::Server(Role::Op),
+        min_args: 1,
+        max_args: 2,
+        handler: ban
+    });
+
+    commands.register(Command {
+        name: "unban",
+        usage: "<nickname | nick:<name> | account:<name> | ip:<address>[/<prefix>]>",
+        description: "Lifts a ban",
+        permission: Permission::Server(Role::Op),
+        min_args: 1,
+        max_args: 1,
+        handler: unban
+    });
+
+    commands.register(Command {
+        name: "bans",
+        usage: "",
+        description: "Lists bans",
+        permission: Permission::Server(Role::Op),
+        min_args: 0,
+        max_args: 0,
+        handler: bans
+    });
+
+    commands.register(Command {
+        name: "stats",
+        usage: "",
+        description: "Shows server counters",
+        permission: Permission::Server(Role::Op),
+        min_args: 0,
+        max_args: 0,
+        handler: stats
+    });
+
+    commands.register(Command {
+        name: "grant",
+        usage: "<account> <owner|op|voice|user>",
+        description: "Sets the server-wide role of a registered account",
+        permission: Permission::Server(Role::Owner),
+        min_args: 2,
+        max_args: 2,
+        handler: grant
+    });
+
+    commands
+}
+
+// Runs a command line from the caller, without its leading '/'
+pub fn dispatch(chat: &mut ChatServer, caller: &Caller, line: &str) {
+    let id: ConnId = caller.id;
+
+    let mut parts = line.trim().splitn(2, char::is_whitespace);
+    let name: String = parts.next().unwrap_or("").to_lowercase();
+    let rest: &str = parts.next().unwrap_or("");
+
+    let command: Command = match chat.commands.get(&name) {
+        Some(command) => command,
+        None => {
+            error(chat, id, &format!("Unknown command /{}. Type /help for a list of commands.", name));
+            return;
+        }
+    };
+
+    if !has_permission(chat, caller, command.permission) {
+        error(chat, id, &format!("You don't have permission to use /{}.", command.name));
+        return;
+    }
+
+    let args: Vec<&str> = split_args(rest, command.max_args);
+    if args.len() < command.min_args || args.len() > command.max_args {
+        error(chat, id, &format!("Usage: {}", command.usage_line()));
+        return;
+    }
+
+    // Arguments aren't logged, they may be passwords
+    debug!(conn:% = id; "Ran /{}", command.name);
+
+    if let Err(reason) = (command.handler)(chat, caller, &args) {
+        error(chat, id, &reason);
+    }
+}
+
+// Splits on whitespace into at most `max_args` arguments; the last one keeps the rest of the line.
+// Returns one extra argument if there are more words than that, so the caller can complain.
+pub fn split_args(line: &str, max_args: usize) -> Vec<&str> {
+    let mut args: Vec<&str> = Vec::new();
+    let mut rest: &str = line.trim();
+
+    while !rest.is_empty() {
+        if args.len() + 1 >= max_args && max_args > 0 {
+            args.push(rest);
+            break;
+        }
+
+        match rest.find(char::is_whitespace) {
+            Some(end) => {
+                args.push(&rest[..end]);
+                rest = rest[end..].trim_start();
+            },
+            None => {
+                args.push(rest);
+                break;
+            }
+        }
+    }
+
+    args
+}
+
+pub fn has_permission(chat: &ChatServer, caller: &Caller, permission: Permission) -> bool {
+    match permission {
+        Permission::Everyone => true,
+        Permission::Channel(role) => {
+            chat.channels.get(&caller.channel).is_some_and(|channel| channel.is_member(caller.id))
+                && chat.channel_role(caller.id, &caller.channel) >= role
+        },
+        Permission::Server(role) => chat.server_role(caller.id) >= role
+    }
+}
+
+// Sends a line back to the issuing connection only
+pub fn reply(chat: &mut ChatServer, id: ConnId, message: &str) {
+    chat.send(id, &Message::command_reply(message));
+}
+
+pub fn error(chat: &mut ChatServer, id: ConnId, message: &str) {
+    chat.send(id, &Message::error(message));
+}
+
+// Replies with "title item, item, ...", wrapped over as many lines as needed to fit in packets
+pub fn reply_list(chat: &mut ChatServer, id: ConnId, title: &str, items: &[String]) {
+    let mut line: String = String::from(title);
+    let mut is_first: bool = true;
+
+    for item in items {
+        if !is_first && line.len() + item.len() + 2 > MAX_REPLY_LENGTH {
+            reply(chat, id, &line);
+            line = String::from("  ");
+            is_first = true;
+        }
+
+        if !is_first {
+            line.push_str(", ");
+        }
+
+        line.push_str(item);
+        is_first = false;
+    }
+
+    reply(chat, id, &line);
+}
+
+fn help(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    let id: ConnId = caller.id;
+
+    if let Some(name) = args.first() {
+        let name: String = name.trim_start_matches('/').to_lowercase();
+        let command: Command = chat.commands.get(&name)
+            .ok_or_else(|| format!("There is no /{} command.", name))?;
+
+        reply(chat, id, &format!("{} - {}", command.usage_line(), command.description));
+        return Ok(());
+    }
+
+    reply(chat, id, "Commands:");
+    for command in chat.commands.all() {
+        if has_permission(chat, caller, command.permission) {
+            reply(chat, id, &format!("  {} - {}", command.usage_line(), command.description));
+        }
+    }
+
+    Ok(())
+}
+
+fn nick(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    chat.rename(caller.id, args[0])
+}
+
+fn register(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    if let Some(account) = chat.users.get(&caller.id).and_then(|user| user.account.clone()) {
+        return Err(format!("You are already logged in as {}.", account));
+    }
+
+    let username: String = chat.nickname(caller.id).unwrap_or_default();
+    chat.accounts.register(&username, args[0])?;
+
+    info!("{} registered an account", username);
+
+    let could_speak: bool = chat.can_speak(caller.id);
+    if let Some(user) = chat.users.get_mut(&caller.id) {
+        user.account = Some(username.clone());
+    }
+
+    reply(chat, caller.id, &format!("Registered {}. From now on, log in with your password to use this nickname.", username));
+
+    if !could_speak {
+        chat.join_default_channel(caller.id);
+    }
+
+    Ok(())
+}
+
+fn login(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    let (username, password) = (args[0], args[1]);
+
+    let account: String = chat.accounts.get(username)
+        .map(|account| account.name.clone())
+        .ok_or_else(|| format!("There is no account called {}.", username))?;
+
+    if chat.users.get(&caller.id).and_then(|user| user.account.as_ref()) == Some(&account) {
+        return Err(format!("You are already logged in as {}.", account));
+    }
+
+    chat.check_nickname(&account, Some(caller.id))?;
+
+    if !chat.accounts.verify(&account, password) {
+        info!(conn:% = caller.id; "Failed to log in as {}", account);
+
+        // Once dropped there is nobody left to tell
+        if chat.count_failed_login(caller.id) {
+            return Err(String::from("Wrong password."));
+        }
+
+        return Ok(());
+    }
+
+    if chat.find_ban(caller.id, Some(&account), Some(&account)).is_some() {
+        return Err(format!("{} is banned.", account));
+    }
+
+    info!(conn:% = caller.id; "Logged in as {}", account);
+
+    let could_speak: bool = chat.can_speak(caller.id);
+    let previous_account: Option<String> = match chat.users.get_mut(&caller.id) {
+        Some(user) => user.account.replace(account.clone()),
+        None => return Ok(())
+    };
+
+    // Taking the account's nickname needs the account, but a login that can't take it doesn't count
+    if chat.nickname(caller.id).as_ref() != Some(&account) {
+        if let Err(reason) = chat.rename(caller.id, &account) {
+            if let Some(user) = chat.users.get_mut(&caller.id) {
+                user.account = previous_account;
+            }
+
+            return Err(reason);
+        }
+    }
+
+    reply(chat, caller.id, &format!("You are now logged in as {}.", account));
+
+    if !could_speak {
+        chat.join_default_channel(caller.id);
+    }
+
+    Ok(())
+}
+
+fn passwd(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    let account: String = chat.users.get(&caller.id)
+        .and_then(|user| user.account.clone())
+        .ok_or_else(|| String::from("You are not logged in."))?;
+
+    if !chat.accounts.verify(&account, args[0]) {
+        return Err(String::from("Your current password is wrong."));
+    }
+
+    chat.accounts.set_password(&account, args[1])?;
+
+    info!("{} changed their password", account);
+    reply(chat, caller.id, "Your password has been changed.");
+
+    Ok(())
+}
+
+fn me(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    let channel: String = chat.member_channel(caller.id, &caller.channel)?;
+    chat.check_can_talk(caller.id, &channel)?;
+
+    let sender: String = chat.nickname(caller.id).unwrap_or_default();
+    info!(target: "chat", conn:% = caller.id; "{} * {} {}", channel, sender, args[0]);
+
+    chat.publish(&channel, &Message::action(&channel, &sender, args[0]))
+}
+
+fn msg(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    chat.direct_message(caller.id, args[0], args[1])
+}
+
+fn who(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    let name: &str = args.first().cloned().unwrap_or(&caller.channel);
+
+    let (name, mut nicknames) = {
+        let channel = chat.channels.get(name)
+            .ok_or_else(|| format!("There is nobody in {}.", name))?;
+
+        let nicknames: Vec<String> = channel.members.iter()
+            .filter_map(|id| chat.nickname(*id).map(|username| {
+                format!("{}{}", chat.channel_role(*id, &channel.name).prefix(), username)
+            }))
+            .collect();
+
+        (channel.name.clone(), nicknames)
+    };
+
+    nicknames.sort_by_key(|nickname| nickname.trim_start_matches(['~', '@', '+']).to_lowercase());
+
+    reply_list(chat, caller.id, &format!("{} ({}): ", name, nicknames.len()), &nicknames);
+
+    Ok(())
+}
+
+fn join(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    chat.join_channel(caller.id, args[0])
+}
+
+fn part(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    let name: &str = args.first().cloned().unwrap_or(&caller.channel);
+    chat.part_channel(caller.id, name)
+}
+
+fn list(chat: &mut ChatServer, caller: &Caller, _args: &[&str]) -> Result<(), String> {
+    let channels: Vec<String> = chat.channels.all().iter()
+        .map(|channel| format!("{} ({})", channel.name, channel.members.len()))
+        .collect();
+
+    if channels.is_empty() {
+        reply(chat, caller.id, "There are no channels. Use /join #channel to start one.");
+    }
+    else {
+        reply_list(chat, caller.id, &format!("Channels ({}): ", channels.len()), &channels);
+    }
+
+    Ok(())
+}
+
+fn topic(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    let name: String = chat.member_channel(caller.id, &caller.channel)?;
+
+    let new_topic: &str = match args.first() {
+        Some(new_topic) => new_topic,
+        None => {
+            let topic: String = chat.channels.get(&name).map(|channel| channel.topic.clone()).unwrap_or_default();
+            let message: String = if topic.is_empty() {
+                format!("{} has no topic.", name)
+            }
+            else {
+                format!("The topic of {} is: {}", name, topic)
+            };
+
+            reply(chat, caller.id, &message);
+            return Ok(());
+        }
+    };
+
+    if !has_permission(chat, caller, Permission::Channel(Role::Op)) {
+        return Err(format!("Only operators of {} can change its topic.", name));
+    }
+
+    if new_topic.len() > MAX_TOPIC_LENGTH {
+        return Err(format!("Topics can be at most {} characters long.", MAX_TOPIC_LENGTH));
+    }
+
+    let username: String = chat.nickname(caller.id).unwrap_or_default();
+    if let Some(channel) = chat.channels.get_mut(&name) {
+        channel.topic = String::from(new_topic);
+    }
+
+    info!("{} changed the topic of {} to {}", username, name, new_topic);
+    chat.send_channel(&name, &Message::Topic { channel: name.clone(), username, topic: String::from(new_topic) });
+
+    Ok(())
+}
+
+fn motd(chat: &mut ChatServer, caller: &Caller, _args: &[&str]) -> Result<(), String> {
+    let motd: String = chat.config.motd.clone();
+    reply(chat, caller.id, &motd);
+
+    Ok(())
+}
+
+fn announce(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    let channel: String = chat.member_channel(caller.id, &caller.channel)?;
+    info!(target: "chat", conn:% = caller.id; "{} announced in {}: {}", chat.nickname(caller.id).unwrap_or_default(), channel, args[0]);
+
+    chat.send_channel(&channel, &Message::notice(&format!("{}: {}", channel, args[0])));
+
+    Ok(())
+}
+
+fn kick(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    let (channel, target_id, target) = outranked_member(chat, caller, args[0])?;
+    let reason: &str = args.get(1).cloned().unwrap_or("");
+    let username: String = chat.nickname(caller.id).unwrap_or_default();
+
+    let notice: String = if reason.is_empty() {
+        format!("{} was kicked from {} by {}.", target, channel, username)
+    }
+    else {
+        format!("{} was kicked from {} by {}: {}", target, channel, username, reason)
+    };
+
+    chat.send_channel(&channel, &Message::notice(&notice));
+    chat.part_channel(target_id, &channel)?;
+    chat.audit.record(&username, "kick", &target, Some(&channel), reason);
+
+    Ok(())
+}
+
+fn mute(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    let minutes: Option<u64> = match args.get(1) {
+        Some(minutes) => match minutes.parse() {
+            Ok(minutes) if minutes > 0 && minutes <= MAX_MUTE_MINUTES => Some(minutes),
+            _ => return Err(format!("Mutes last 1 to {} minutes. Leave the minutes out to mute until /unmute.", MAX_MUTE_MINUTES))
+        },
+        None => None
+    };
+
+    let (channel, target_id, target) = outranked_member(chat, caller, args[0])?;
+    let username: String = chat.nickname(caller.id).unwrap_or_default();
+
+    let mute = Mute {
+        nickname: target.clone(),
+        account: chat.users.get(&target_id).and_then(|user| user.account.clone()),
+        until: minutes.map(|minutes| Instant::now() + Duration::from_secs(minutes * 60))
+    };
+    if let Some(found) = chat.channels.get_mut(&channel) {
+        found.mute(mute);
+    }
+
+    let duration: String = match minutes {
+        Some(minutes) => format!(" for {} minute{}", minutes, if minutes == 1 { "" } else { "s" }),
+        None => String::new()
+    };
+
+    chat.send(target_id, &Message::notice(&format!("{} muted you in {}{}.", username, channel, duration)));
+    reply(chat, caller.id, &format!("Muted {} in {}{}.", target, channel, duration));
+    chat.audit.record(&username, "mute", &target, Some(&channel), duration.trim());
+
+    Ok(())
+}
+
+fn unmute(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    let channel: String = chat.member_channel(caller.id, &caller.channel)?;
+    // Mutes outlast the connection, so whoever it was may be offline
+    let target_id: Option<ConnId> = chat.find_user(args[0]);
+    let target: String = target_id.and_then(|id| chat.nickname(id)).unwrap_or_else(|| String::from(args[0]));
+
+    let account: Option<String> = target_id.and_then(|id| chat.users.get(&id)).and_then(|user| user.account.clone());
+    let was_muted: bool = chat.channels.get_mut(&channel)
+        .is_some_and(|found| found.unmute(&target, account.as_deref()));
+
+    if !was_muted {
+        return Err(format!("{} is not muted in {}.", target, channel));
+    }
+
+    let username: String = chat.nickname(caller.id).unwrap_or_default();
+    if let Some(target_id) = target_id {
+        chat.send(target_id, &Message::notice(&format!("{} unmuted you in {}.", username, channel)));
+    }
+
+    reply(chat, caller.id, &format!("Unmuted {} in {}.", target, channel));
+    chat.audit.record(&username, "unmute", &target, Some(&channel), "");
+
+    Ok(())
+}
+
+fn op(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    set_channel_role(chat, caller, args[0], Role::Op)
+}
+
+fn voice(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    set_channel_role(chat, caller, args[0], Role::Voice)
+}
+
+fn deop(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    set_channel_role(chat, caller, args[0], Role::User)
+}
+
+fn moderate(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    let moderated: bool = match args[0].to_lowercase().as_str() {
+        "on" => true,
+        "off" => false,
+        _ => return Err(String::from("Usage: /moderate <on|off>"))
+    };
+
+    let channel: String = chat.member_channel(caller.id, &caller.channel)?;
+    if let Some(found) = chat.channels.get_mut(&channel) {
+        found.moderated = moderated;
+    }
+
+    let username: String = chat.nickname(caller.id).unwrap_or_default();
+    let notice: String = if moderated {
+        format!("{} made {} moderated. Only voiced members can talk.", username, channel)
+    }
+    else {
+        format!("{} is no longer moderated.", channel)
+    };
+
+    chat.send_channel(&channel, &Message::notice(&notice));
+    chat.audit.record(&username, if moderated { "moderate" } else { "unmoderate" }, &channel, Some(&channel), "");
+
+    Ok(())
+}
+
+fn ban(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    let target: BanTarget = parse_ban_target(args[0])?;
+    let reason: &str = args.get(1).cloned().unwrap_or("");
+    let username: String = chat.nickname(caller.id).unwrap_or_default();
+
+    // Bans can't reach yourself or anyone at your own server rank or above
+    let role: Role = chat.server_role(caller.id);
+    let protected: Option<String> = chat.users.iter()
+        .filter(|&(_, user)| target.matches(user.username.as_deref(), user.account.as_deref(), user.ip))
+        .find(|&(id, _)| *id == caller.id || chat.server_role(*id) >= role)
+        .map(|(id, _)| chat.nickname(*id).unwrap_or_default());
+
+    if let Some(protected) = protected {
+        return Err(format!("{} would also ban {}.", target, protected));
+    }
+
+    chat.bans.add(Ban {
+        target: target.clone(),
+        reason: String::from(reason),
+        by: username.clone(),
+        created: SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0)
+    })?;
+
+    chat.audit.record(&username, "ban", &target.to_string(), None, reason);
+    reply(chat, caller.id, &format!("Banned {}.", target));
+    chat.enforce_bans();
+
+    Ok(())
+}
+
+fn unban(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    let target: BanTarget = parse_ban_target(args[0])?;
+    chat.bans.remove(&target)?;
+
+    let username: String = chat.nickname(caller.id).unwrap_or_default();
+    chat.audit.record(&username, "unban", &target.to_string(), None, "");
+    reply(chat, caller.id, &format!("Unbanned {}.", target));
+
+    Ok(())
+}
+
+fn bans(chat: &mut ChatServer, caller: &Caller, _args: &[&str]) -> Result<(), String> {
+    let lines: Vec<String> = chat.bans.all().iter()
+        .map(|ban| format!("  {}", ban))
+        .collect();
+
+    if lines.is_empty() {
+        reply(chat, caller.id, "Nobody is banned.");
+        return Ok(());
+    }
+
+    reply(chat, caller.id, &format!("Bans ({}):", lines.len()));
+    for line in lines {
+        reply(chat, caller.id, &line);
+    }
+
+    Ok(())
+}
+
+fn stats(chat: &mut ChatServer, caller: &Caller, _args: &[&str]) -> Result<(), String> {
+    for line in chat.stats() {
+        reply(chat, caller.id, &line);
+    }
+
+    Ok(())
+}
+
+fn grant(chat: &mut ChatServer, caller: &Caller, args: &[&str]) -> Result<(), String> {
+    let role: Role = args[1].parse()?;
+    let account: String = chat.accounts.get(args[0])
+        .map(|account| account.name.clone())
+        .ok_or_else(|| format!("There is no account called {}.", args[0]))?;
+
+    chat.accounts.set_role(&account, role)?;
+
+    let username: String = chat.nickname(caller.id).unwrap_or_default();
+    chat.audit.record(&username, "grant", &account, None, &role.to_string());
+    reply(chat, caller.id, &format!("{} is now a server {}.", account, role));
+
+    Ok(())
+}
+
+// A bare nickname bans that nickname
+pub fn parse_ban_target(target: &str) -> Result<BanTarget, String> {
+    if target.contains(':') {
+        target.parse()
+    }
+    else {
+        Ok(BanTarget::Nickname(String::from(target)))
+    }
+}
+
+// The caller's channel and a member of it whose role is below the caller's
+fn outranked_member(chat: &ChatServer, caller: &Caller, nickname: &str) -> Result<(String, ConnId, String), String> {
+    let channel: String = chat.member_channel(caller.id, &caller.channel)?;
+    let target_id: ConnId = match chat.find_user(nickname) {
+        Some(id) if chat.channels.get(&channel).is_some_and(|found| found.is_member(id)) => id,
+        _ => return Err(format!("{} is not in {}.", nickname, channel))
+    };
+
+    if target_id == caller.id {
+        return Err(String::from("You can't do that to yourself."));
+    }
+
+    if chat.channel_role(target_id, &channel) >= chat.channel_role(caller.id, &channel) {
+        return Err(format!("{} outranks or equals you in {}.", nickname, channel));
+    }
+
+    Ok((channel, target_id, chat.nickname(target_id).unwrap_or_default()))
+}
+
+// Changes someone's role in the caller's channel. Only roles below the caller's own can be handed out.
+fn set_channel_role(chat: &mut ChatServer, caller: &Caller, nickname: &str, role: Role) -> Result<(), String> {
+    let (channel, target_id, target) = outranked_member(chat, caller, nickname)?;
+
+    if role >= chat.channel_role(caller.id, &channel) {
+        return Err(format!("Only those above {} in {} can hand it out.", role, channel));
+    }
+
+    if let Some(found) = chat.channels.get_mut(&channel) {
+        found.set_role(target_id, role);
+    }
+
+    let username: String = chat.nickname(caller.id).unwrap_or_default();
+    let notice: String = if role == Role::User {
+        format!("{} took away {}'s role in {}.", username, target, channel)
+    }
+    else {
+        format!("{} made {} {} of {}.", username, target, role, channel)
+    };
+
+    chat.send_channel(&channel, &Message::notice(&notice));
+    let action: String = if role == Role::User { String::from("deop") } else { role.to_string() };
+    chat.audit.record(&username, &action, &target, Some(&channel), "");
+
+    Ok(())
+}
@@ -0,0 +1,397 @@
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml;
+use doosknet::{serialize_packet, Heartbeat, Message, CAPABILITIES, MAX_PACKET_BODY_SIZE, MAX_PACKET_SIZE, PACKET_HEADER_SIZE};
+use channels;
+use nickname;
+use logger;
+use history::MAX_HISTORY_PAGE;
+
+pub use logger::LogFileConfig;
+
+pub static DEFAULT_CONFIG_PATH: &str = "rustychat.toml";
+
+const MAX_SERVER_NAME_LENGTH: usize = 32;
+
+pub static USAGE: &str = "Usage: rustychat-server [options]
+
+Options:
+    --config <path>                 Read settings from a TOML file (default: rustychat.toml, if present)
+    --bind <addr>                   Address to listen on, e.g. 0.0.0.0:7667 or [::]:7667. Repeat for several.
+    --name <name>                   Server name shown to clients
+    --motd <text>                   Message of the day
+    --default-channel <#name>       Channel everyone joins when they connect (empty for none)
+    --require-auth <true|false>     Only registered users may talk and join channels
+    --owner <account>               Account that owns the server. Repeat for several.
+    --history-replay <n>            Number of past messages sent when joining a channel
+    --max-connections <n>           Maximum number of simultaneous connections
+    --max-connections-per-ip <n>    Maximum number of connections from a single address (0 = unlimited)
+    --event-capacity <n>            Number of socket events handled per poll
+    --heartbeat <seconds>           Ping clients that have been quiet this long (0 = never)
+    --heartbeat-timeout <seconds>   Drop clients that have been quiet this long
+    --handshake-timeout <seconds>   Drop connections that haven't picked a nickname after this long
+    --idle-timeout <seconds>        Drop users who haven't sent anything for this long (0 = never)
+    --shutdown-timeout <seconds>    How long to wait for queued messages to go out when shutting down
+    --console <true|false>          Read admin commands typed into the terminal (default: true)
+    --log-level <filter>            off, error, warn, info, debug or trace, optionally followed by levels
+                                    for single modules, e.g. info,doosknet=debug,chat=info
+    --log-file <path>               Also log to this file as JSON lines, rotated as it grows
+    --data-dir <path>               Where the server keeps its data
+    --tls-cert <path>               PEM certificate chain, enables TLS
+    --tls-key <path>                PEM private key for --tls-cert
+    --help                          Show this message
+
+Command line options override the config file.";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind: Vec<SocketAddr>,
+    pub server_name: String,
+    pub motd: String,
+    pub default_channel: String,
+    pub history_replay: usize,
+    pub require_auth: bool,
+    // Accounts with the owner role on the whole server, whatever their stored role
+    pub owners: Vec<String>,
+    pub max_connections: usize,
+    pub max_connections_per_ip: usize,
+    pub event_capacity: usize,
+    // Heartbeats find connections whose other end has vanished without closing them
+    pub heartbeat_seconds: u64,
+    pub heartbeat_timeout_seconds: u64,
+    pub handshake_timeout_seconds: u64,
+    // Pings and pongs don't count as activity
+    pub idle_timeout_seconds: u64,
+    // On SIGINT or SIGTERM, how long to keep delivering what is already queued before giving up
+    pub shutdown_timeout_seconds: u64,
+    // Read admin commands from stdin, see console.rs
+    pub console: bool,
+    // A default level plus per-module levels, see logger::Filter. Chat messages are logged under "chat".
+    pub log_level: String,
+    pub log_file: Option<LogFileConfig>,
+    pub data_dir: PathBuf,
+    pub flood: FloodConfig,
+    pub tls: Option<TlsConfig>
+}
+
+// Limits on what each client may send. A rate of 0 turns that limit off.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FloodConfig {
+    // Per connection
+    pub messages_per_second: f64,
+    pub message_burst: u32,
+    pub bytes_per_second: u32,
+    pub byte_burst: u32,
+    // Shared by all connections from one address
+    pub ip_messages_per_second: f64,
+    pub ip_message_burst: u32,
+    pub ip_bytes_per_second: u32,
+    pub ip_byte_burst: u32,
+    // Going over a limit is a strike. The first one gets a warning, later ones a mute and then a disconnect.
+    // 0 turns that step off.
+    pub mute_after: u32,
+    pub mute_seconds: u64,
+    pub disconnect_after: u32,
+    // How long it takes for one strike to be forgotten
+    pub strike_decay_seconds: u64
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: vec!["0.0.0.0:7667".parse().unwrap()],
+            server_name: String::from("Rustychat"),
+            motd: String::from("Welcome to Rustychat!"),
+            default_channel: String::from("#general"),
+            history_replay: 20,
+            require_auth: false,
+            owners: Vec::new(),
+            max_connections: 256,
+            max_connections_per_ip: 8,
+            event_capacity: 1024,
+            heartbeat_seconds: 30,
+            heartbeat_timeout_seconds: 90,
+            handshake_timeout_seconds: 30,
+            idle_timeout_seconds: 0,
+            shutdown_timeout_seconds: 5,
+            console: true,
+            log_level: String::from("info,chat=off"),
+            log_file: None,
+            data_dir: PathBuf::from("data"),
+            flood: FloodConfig::default(),
+            tls: None
+        }
+    }
+}
+
+impl Default for FloodConfig {
+    fn default() -> Self {
+        FloodConfig {
+            messages_per_second: 5.0,
+            message_burst: 10,
+            bytes_per_second: 2048,
+            byte_burst: 8192,
+            ip_messages_per_second: 15.0,
+            ip_message_burst: 30,
+            ip_bytes_per_second: 8192,
+            ip_byte_burst: 32768,
+            mute_after: 5,
+            mute_seconds: 60,
+            disconnect_after: 15,
+            strike_decay_seconds: 10
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    // Reading or parsing the config file failed
+    File(PathBuf, String),
+    // A command line option was unknown or malformed
+    Argument(String),
+    // The settings parsed, but don't make sense together
+    Invalid(String)
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::File(ref path, ref reason) => write!(f, "Failed to load {}: {}", path.display(), reason),
+            ConfigError::Argument(ref reason) => write!(f, "{}", reason),
+            ConfigError::Invalid(ref reason) => write!(f, "Invalid configuration: {}", reason)
+        }
+    }
+}
+
+impl Config {
+    // Settings from the config file (if any), overridden by the command line, then validated.
+    // Returns Ok(None) when the user only asked for --help.
+    pub fn load(args: &[String]) -> Result<Option<Config>, ConfigError> {
+        if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+            return Ok(None);
+        }
+
+        let mut config: Config = match config_path(args)? {
+            Some(path) => Config::from_file(&path)?,
+            None => Config::default()
+        };
+
+        config.apply_args(args)?;
+        config.validate()?;
+
+        Ok(Some(config))
+    }
+
+    pub fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let text: String = fs::read_to_string(path)
+            .map_err(|e| ConfigError::File(path.to_path_buf(), e.to_string()))?;
+
+        toml::from_str(&text)
+            .map_err(|e| ConfigError::File(path.to_path_buf(), e.to_string()))
+    }
+
+    fn apply_args(&mut self, args: &[String]) -> Result<(), ConfigError> {
+        let mut bind: Vec<SocketAddr> = Vec::new();
+        let mut owners: Vec<String> = Vec::new();
+        let mut tls_cert: Option<PathBuf> = None;
+        let mut tls_key: Option<PathBuf> = None;
+
+        let mut i: usize = 1;
+        while i < args.len() {
+            let name: &str = &args[i];
+            let value: &str = match args.get(i + 1) {
+                Some(value) => value,
+                None => return Err(ConfigError::Argument(format!("Missing value for {}", name)))
+            };
+
+            match name {
+                "--config" => (),
+                "--bind" => bind.push(parse_value(name, value)?),
+                "--name" => self.server_name = String::from(value),
+                "--motd" => self.motd = String::from(value),
+                "--default-channel" => self.default_channel = String::from(value),
+                "--require-auth" => self.require_auth = parse_value(name, value)?,
+                "--owner" => owners.push(String::from(value)),
+                "--history-replay" => self.history_replay = parse_value(name, value)?,
+                "--max-connections" => self.max_connections = parse_value(name, value)?,
+                "--max-connections-per-ip" => self.max_connections_per_ip = parse_value(name, value)?,
+                "--event-capacity" => self.event_capacity = parse_value(name, value)?,
+                "--heartbeat" => self.heartbeat_seconds = parse_value(name, value)?,
+                "--heartbeat-timeout" => self.heartbeat_timeout_seconds = parse_value(name, value)?,
+                "--handshake-timeout" => self.handshake_timeout_seconds = parse_value(name, value)?,
+                "--idle-timeout" => self.idle_timeout_seconds = parse_value(name, value)?,
+                "--shutdown-timeout" => self.shutdown_timeout_seconds = parse_value(name, value)?,
+                "--console" => self.console = parse_value(name, value)?,
+                "--log-level" => self.log_level = String::from(value),
+                "--log-file" => {
+                    let log_file: LogFileConfig = self.log_file.take().unwrap_or_default();
+                    self.log_file = Some(LogFileConfig { path: PathBuf::from(value), ..log_file });
+                },
+                "--data-dir" => self.data_dir = PathBuf::from(value),
+                "--tls-cert" => tls_cert = Some(PathBuf::from(value)),
+                "--tls-key" => tls_key = Some(PathBuf::from(value)),
+                _ => return Err(ConfigError::Argument(format!("Unknown option {}", name)))
+            }
+
+            i += 2;
+        }
+
+        // Addresses given on the command line replace the ones from the file
+        if !bind.is_empty() {
+            self.bind = bind;
+        }
+
+        if !owners.is_empty() {
+            self.owners = owners;
+        }
+
+        match (tls_cert, tls_key) {
+            (Some(cert), Some(key)) => self.tls = Some(TlsConfig { cert, key }),
+            (None, None) => (),
+            _ => return Err(ConfigError::Argument(String::from("--tls-cert and --tls-key must be used together")))
+        }
+
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.bind.is_empty() {
+            return Err(ConfigError::Invalid(String::from("at least one bind address is required")));
+        }
+
+        if self.server_name.trim().is_empty() || self.server_name.len() > MAX_SERVER_NAME_LENGTH {
+            return Err(ConfigError::Invalid(format!("server_name must be 1 to {} characters", MAX_SERVER_NAME_LENGTH)));
+        }
+
+        let max_motd_length: usize = max_motd_length(&self.server_name);
+        if self.motd.len() > max_motd_length {
+            return Err(ConfigError::Invalid(format!("motd must be at most {} bytes with this server_name, to fit in a Welcome packet", max_motd_length)));
+        }
+
+        if !self.default_channel.is_empty() {
+            channels::validate_name(&self.default_channel)
+                .map_err(|reason| ConfigError::Invalid(format!("default_channel: {}", reason)))?;
+        }
+
+        for owner in &self.owners {
+            nickname::validate(owner)
+                .map_err(|reason| ConfigError::Invalid(format!("owners: {}", reason)))?;
+        }
+
+        if self.history_replay > MAX_HISTORY_PAGE {
+            return Err(ConfigError::Invalid(format!("history_replay must be at most {}", MAX_HISTORY_PAGE)));
+        }
+
+        if self.max_connections == 0 {
+            return Err(ConfigError::Invalid(String::from("max_connections must be at least 1")));
+        }
+
+        if self.event_capacity == 0 {
+            return Err(ConfigError::Invalid(String::from("event_capacity must be at least 1")));
+        }
+
+        if self.heartbeat_seconds > 0 && self.heartbeat_timeout_seconds <= self.heartbeat_seconds {
+            return Err(ConfigError::Invalid(String::from("heartbeat_timeout_seconds must be longer than heartbeat_seconds")));
+        }
+
+        if self.handshake_timeout_seconds == 0 {
+            return Err(ConfigError::Invalid(String::from("handshake_timeout_seconds must be at least 1")));
+        }
+
+        logger::Filter::parse(&self.log_level)
+            .map_err(|reason| ConfigError::Invalid(format!("log_level: {}", reason)))?;
+
+        self.flood.validate()?;
+
+        if let Err(e) = fs::create_dir_all(&self.data_dir) {
+            return Err(ConfigError::Invalid(format!("data_dir {} is not usable: {}", self.data_dir.display(), e)));
+        }
+
+        Ok(())
+    }
+
+    // None when heartbeats are turned off
+    pub fn heartbeat(&self) -> Option<Heartbeat> {
+        if self.heartbeat_seconds == 0 {
+            return None;
+        }
+
+        Some(Heartbeat {
+            interval: Duration::from_secs(self.heartbeat_seconds),
+            timeout: Duration::from_secs(self.heartbeat_timeout_seconds)
+        })
+    }
+}
+
+impl FloodConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let limits = [
+            ("messages_per_second", self.messages_per_second, self.message_burst, 1),
+            ("bytes_per_second", self.bytes_per_second as f64, self.byte_burst, MAX_PACKET_SIZE as u32),
+            ("ip_messages_per_second", self.ip_messages_per_second, self.ip_message_burst, 1),
+            ("ip_bytes_per_second", self.ip_bytes_per_second as f64, self.ip_byte_burst, MAX_PACKET_SIZE as u32)
+        ];
+
+        for &(name, rate, burst, min_burst) in &limits {
+            if rate < 0.0 || !rate.is_finite() {
+                return Err(ConfigError::Invalid(format!("flood.{} must be 0 or more", name)));
+            }
+
+            // Otherwise the bucket could never hold enough for a single packet
+            if rate > 0.0 && burst < min_burst {
+                return Err(ConfigError::Invalid(format!("the burst for flood.{} must be at least {}", name, min_burst)));
+            }
+        }
+
+        if self.mute_after > 0 && self.mute_seconds == 0 {
+            return Err(ConfigError::Invalid(String::from("flood.mute_seconds must be at least 1")));
+        }
+
+        Ok(())
+    }
+}
+
+// --config if given, otherwise the default file when it exists
+fn config_path(args: &[String]) -> Result<Option<PathBuf>, ConfigError> {
+    if let Some(i) = args.iter().position(|arg| arg == "--config") {
+        return match args.get(i + 1) {
+            Some(path) => Ok(Some(PathBuf::from(path))),
+            None => Err(ConfigError::Argument(String::from("Missing value for --config")))
+        };
+    }
+
+    let path: &Path = Path::new(DEFAULT_CONFIG_PATH);
+    if path.exists() {
+        Ok(Some(path.to_path_buf()))
+    }
+    else {
+        Ok(None)
+    }
+}
+
+fn parse_value<T: ::std::str::FromStr>(name: &str, value: &str) -> Result<T, ConfigError> {
+    value.parse()
+        .map_err(|_| ConfigError::Argument(format!("Invalid value for {}: {}", name, value)))
+}
+
+// The longest MOTD, in bytes, that still fits in a Welcome packet to the longest nickname with every capability
+pub fn max_motd_length(server_name: &str) -> usize {
+    let capabilities: Vec<String> = CAPABILITIES.iter().map(|c| String::from(*c)).collect();
+    let nickname: String = "x".repeat(nickname::MAX_NICKNAME_LENGTH);
+    let welcome = Message::welcome(&nickname, capabilities, server_name, "");
+
+    let body_size: usize = serialize_packet(&welcome).map_or(MAX_PACKET_BODY_SIZE, |data| data.len() - PACKET_HEADER_SIZE);
+    (MAX_PACKET_BODY_SIZE - 1).saturating_sub(body_size)
+}
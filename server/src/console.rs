@@ -0,0 +1,342 @@
+use std::io::{self, BufRead};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use doosknet::*;
+use chat::ChatServer;
+use config::Config;
+use bans::{Ban, BanTarget, Bans};
+use commands::{parse_ban_target, split_args};
+
+// Who console commands are recorded as in the audit log
+const ACTOR: &str = "console";
+
+// Runs a console command. The lines it returns are printed for the operator.
+type Handler = fn(&Console, &mut ChatServer, &[&str]) -> Result<Vec<String>, String>;
+
+struct ConsoleCommand {
+    name: &'static str,
+    usage: &'static str,
+    description: &'static str,
+    // How many arguments the command takes. The last one gets the rest of the line, spaces included.
+    min_args: usize,
+    max_args: usize,
+    handler: Handler
+}
+
+impl ConsoleCommand {
+    fn usage_line(&self) -> String {
+        if self.usage.is_empty() {
+            String::from(self.name)
+        }
+        else {
+            format!("{} {}", self.name, self.usage)
+        }
+    }
+}
+
+static COMMANDS: &[ConsoleCommand] = &[
+    ConsoleCommand {
+        name: "help",
+        usage: "",
+        description: "Lists console commands",
+        min_args: 0,
+        max_args: 0,
+        handler: help
+    },
+    ConsoleCommand {
+        name: "list",
+        usage: "",
+        description: "Lists everyone connected",
+        min_args: 0,
+        max_args: 0,
+        handler: list
+    },
+    ConsoleCommand {
+        name: "kick",
+        usage: "<nickname> [reason]",
+        description: "Disconnects someone from the server",
+        min_args: 1,
+        max_args: 2,
+        handler: kick
+    },
+    ConsoleCommand {
+        name: "broadcast",
+        usage: "<message>",
+        description: "Sends a notice to everyone",
+        min_args: 1,
+        max_args: 1,
+        handler: broadcast
+    },
+    ConsoleCommand {
+        name: "ban",
+        usage: "<nickname|nick:name|account:name|ip:address[/prefix]> [reason]",
+        description: "Bans someone from the server and disconnects them",
+        min_args: 1,
+        max_args: 2,
+        handler: ban
+    },
+    ConsoleCommand {
+        name: "unban",
+        usage: "<nickname|nick:name|account:name|ip:address[/prefix]>",
+        description: "Lifts a ban",
+        min_args: 1,
+        max_args: 1,
+        handler: unban
+    },
+    ConsoleCommand {
+        name: "bans",
+        usage: "",
+        description: "Lists bans",
+        min_args: 0,
+        max_args: 0,
+        handler: bans
+    },
+    ConsoleCommand {
+        name: "stats",
+        usage: "",
+        description: "Shows server statistics",
+        min_args: 0,
+        max_args: 0,
+        handler: stats
+    },
+    ConsoleCommand {
+        name: "reload",
+        usage: "",
+        description: "Reloads the config file and the ban list",
+        min_args: 0,
+        max_args: 0,
+        handler: reload
+    },
+    ConsoleCommand {
+        name: "shutdown",
+        usage: "",
+        description: "Shuts the server down, the same way as Ctrl-C",
+        min_args: 0,
+        max_args: 0,
+        handler: shutdown
+    }
+];
+
+// Admin commands typed into the terminal the server runs in. Stdin is read on a thread of its own,
+// which hands each line to the main loop and wakes it up.
+pub struct Console {
+    lines: Receiver<String>,
+    // The server's command line, so `reload` loads the settings the same way they were first loaded
+    args: Vec<String>,
+    // Shared with the signal handlers. Once set, the main loop shuts the server down.
+    stop: Arc<AtomicBool>
+}
+
+impl Console {
+    pub fn start(args: Vec<String>, stop: Arc<AtomicBool>, waker: Waker) -> io::Result<Console> {
+        let (sender, lines) = mpsc::channel();
+
+        thread::Builder::new()
+            .name(String::from("console"))
+            .spawn(move || read_lines(sender, waker))?;
+
+        Ok(Console {
+            lines,
+            args,
+            stop
+        })
+    }
+
+    // Runs every command typed since the last call. Called from the main loop.
+    pub fn run_pending(&self, chat: &mut ChatServer) {
+        let lines: Vec<String> = self.lines.try_iter().collect();
+        for line in lines {
+            self.run(chat, &line);
+        }
+    }
+
+    fn run(&self, chat: &mut ChatServer, line: &str) {
+        let mut parts = line.trim().splitn(2, char::is_whitespace);
+        let name: String = parts.next().unwrap_or("").to_lowercase();
+        let rest: &str = parts.next().unwrap_or("");
+
+        if name.is_empty() {
+            return;
+        }
+
+        let command: &ConsoleCommand = match COMMANDS.iter().find(|command| command.name == name) {
+            Some(command) => command,
+            None => {
+                println!("Unknown command {}. Type help for a list of commands.", name);
+                return;
+            }
+        };
+
+        let args: Vec<&str> = split_args(rest, command.max_args);
+        if args.len() < command.min_args || args.len() > command.max_args {
+            println!("Usage: {}", command.usage_line());
+            return;
+        }
+
+        match (command.handler)(self, chat, &args) {
+            Ok(lines) => {
+                for line in lines {
+                    println!("{}", line);
+                }
+            },
+            Err(reason) => println!("{}", reason)
+        }
+    }
+}
+
+// Runs on the console thread until stdin is closed, or the main loop has gone
+fn read_lines(sender: Sender<String>, waker: Waker) {
+    let stdin = io::stdin();
+
+    for line in stdin.lock().lines() {
+        let line: String = match line {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Stopped reading console commands: {}", e);
+                return;
+            }
+        };
+
+        if sender.send(line).is_err() {
+            return;
+        }
+
+        if let Err(e) = waker.wake() {
+            warn!("Failed to wake the main loop: {}", e);
+        }
+    }
+
+    debug!("The console was closed");
+}
+
+fn help(_console: &Console, _chat: &mut ChatServer, _args: &[&str]) -> Result<Vec<String>, String> {
+    Ok(COMMANDS.iter()
+        .map(|command| format!("  {} - {}", command.usage_line(), command.description))
+        .collect())
+}
+
+fn list(_console: &Console, chat: &mut ChatServer, _args: &[&str]) -> Result<Vec<String>, String> {
+    let mut ids: Vec<ConnId> = chat.users.keys().cloned().collect();
+    ids.sort();
+
+    let mut lines: Vec<String> = vec![format!("Connections ({}):", ids.len())];
+    for id in ids {
+        let user = &chat.users[&id];
+
+        let mut line: String = match user.username {
+            Some(ref username) => format!("  {} {}{} from {}", id, chat.server_role(id).prefix(), username, user.ip),
+            None => format!("  {} (connecting) from {}", id, user.ip)
+        };
+
+        if let Some(ref account) = user.account {
+            line.push_str(&format!(", account {}", account));
+        }
+
+        let channels: Vec<String> = chat.channels.joined_by(id);
+        if !channels.is_empty() {
+            line.push_str(&format!(", in {}", channels.join(" ")));
+        }
+
+        lines.push(line);
+    }
+
+    Ok(lines)
+}
+
+fn kick(_console: &Console, chat: &mut ChatServer, args: &[&str]) -> Result<Vec<String>, String> {
+    let id: ConnId = chat.find_user(args[0]).ok_or_else(|| format!("Nobody is using the nickname {}.", args[0]))?;
+    let username: String = chat.nickname(id).unwrap_or_default();
+    let reason: &str = args.get(1).cloned().unwrap_or("");
+
+    for name in chat.channels.joined_by(id) {
+        chat.send_channel(&name, &Message::notice(&format!("{} was kicked from the server.", username)));
+    }
+
+    if reason.is_empty() {
+        chat.reject(id, "You were kicked from the server.");
+    }
+    else {
+        chat.reject(id, &format!("You were kicked from the server: {}", reason));
+    }
+
+    chat.audit.record(ACTOR, "kick", &username, None, reason);
+
+    Ok(vec![format!("Kicked {}.", username)])
+}
+
+fn broadcast(_console: &Console, chat: &mut ChatServer, args: &[&str]) -> Result<Vec<String>, String> {
+    info!("Broadcast: {}", args[0]);
+    chat.send_everyone(&Message::notice(args[0]));
+
+    Ok(Vec::new())
+}
+
+fn ban(_console: &Console, chat: &mut ChatServer, args: &[&str]) -> Result<Vec<String>, String> {
+    let target: BanTarget = parse_ban_target(args[0])?;
+    let reason: &str = args.get(1).cloned().unwrap_or("");
+
+    chat.bans.add(Ban {
+        target: target.clone(),
+        reason: String::from(reason),
+        by: String::from(ACTOR),
+        created: SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0)
+    })?;
+
+    chat.audit.record(ACTOR, "ban", &target.to_string(), None, reason);
+    chat.enforce_bans();
+
+    Ok(vec![format!("Banned {}.", target)])
+}
+
+fn unban(_console: &Console, chat: &mut ChatServer, args: &[&str]) -> Result<Vec<String>, String> {
+    let target: BanTarget = parse_ban_target(args[0])?;
+    chat.bans.remove(&target)?;
+    chat.audit.record(ACTOR, "unban", &target.to_string(), None, "");
+
+    Ok(vec![format!("Unbanned {}.", target)])
+}
+
+fn bans(_console: &Console, chat: &mut ChatServer, _args: &[&str]) -> Result<Vec<String>, String> {
+    if chat.bans.all().is_empty() {
+        return Ok(vec![String::from("Nobody is banned.")]);
+    }
+
+    let mut lines: Vec<String> = vec![format!("Bans ({}):", chat.bans.all().len())];
+    lines.extend(chat.bans.all().iter().map(|ban| format!("  {}", ban)));
+
+    Ok(lines)
+}
+
+fn stats(_console: &Console, chat: &mut ChatServer, _args: &[&str]) -> Result<Vec<String>, String> {
+    Ok(chat.stats())
+}
+
+// Reads the config file and command line again, and the ban list in case it was edited by hand
+fn reload(console: &Console, chat: &mut ChatServer, _args: &[&str]) -> Result<Vec<String>, String> {
+    let config: Config = Config::load(&console.args)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| String::from("Nothing to reload."))?;
+
+    let bans: Bans = Bans::open(&chat.config.data_dir)
+        .map_err(|e| format!("Failed to load bans from {}: {}", chat.config.data_dir.display(), e))?;
+
+    let needs_restart: Vec<&'static str> = chat.reload(config);
+    chat.bans = bans;
+    chat.enforce_bans();
+
+    let mut lines: Vec<String> = vec![String::from("Reloaded the config and bans.")];
+    if !needs_restart.is_empty() {
+        lines.push(format!("Restart the server to change {}.", needs_restart.join(", ")));
+    }
+
+    Ok(lines)
+}
+
+fn shutdown(console: &Console, _chat: &mut ChatServer, _args: &[&str]) -> Result<Vec<String>, String> {
+    console.stop.store(true, Ordering::SeqCst);
+
+    Ok(Vec::new())
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use doosknet::ConnId;
+use config::FloodConfig;
+
+// Refills at `rate` tokens a second, up to `capacity`. A rate of 0 never runs out.
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    updated: Instant
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, capacity: f64) -> Self {
+        TokenBucket {
+            rate,
+            capacity,
+            tokens: capacity,
+            updated: Instant::now()
+        }
+    }
+
+    // Takes `amount` tokens if there are enough of them
+    pub fn take(&mut self, amount: f64, now: Instant) -> bool {
+        if self.rate <= 0.0 {
+            return true;
+        }
+
+        let elapsed: f64 = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = now;
+
+        if self.tokens < amount {
+            return false;
+        }
+
+        self.tokens -= amount;
+        true
+    }
+}
+
+// A message bucket and a byte bucket, both of which must allow a packet through
+struct Limiter {
+    messages: TokenBucket,
+    bytes: TokenBucket
+}
+
+impl Limiter {
+    fn new(messages_per_second: f64, message_burst: u32, bytes_per_second: u32, byte_burst: u32) -> Self {
+        Limiter {
+            messages: TokenBucket::new(messages_per_second, message_burst as f64),
+            bytes: TokenBucket::new(bytes_per_second as f64, byte_burst as f64)
+        }
+    }
+
+    fn take(&mut self, bytes: usize, now: Instant) -> bool {
+        // Take from both, so a packet that is refused still costs what it would have
+        let has_messages: bool = self.messages.take(1.0, now);
+        let has_bytes: bool = self.bytes.take(bytes as f64, now);
+        has_messages && has_bytes
+    }
+}
+
+struct Connection {
+    ip: IpAddr,
+    limiter: Limiter,
+    // Times the connection went over its limits recently. Drops by one every `strike_decay_seconds`.
+    strikes: u32,
+    last_strike: Instant,
+    muted_until: Option<Instant>
+}
+
+// Shared by every connection from one address
+struct Address {
+    limiter: Limiter,
+    connections: usize
+}
+
+// What to do with a packet
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    Allow,
+    // Over the limit: drop the packet
+    Throttle,
+    // Over the limit for the first time in a while: drop it and tell them to slow down
+    Warn,
+    // Kept at it: drop it, and stop them from talking for a while
+    Mute(Duration),
+    // Still at it: drop the connection
+    Disconnect
+}
+
+// Running totals, for /stats
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FloodStats {
+    pub packets: u64,
+    pub bytes: u64,
+    pub dropped: u64,
+    pub warnings: u64,
+    pub mutes: u64,
+    pub disconnects: u64
+}
+
+// Rate limits on what clients send, per connection and per address
+pub struct FloodControl {
+    config: FloodConfig,
+    connections: HashMap<ConnId, Connection>,
+    addresses: HashMap<IpAddr, Address>,
+    pub stats: FloodStats
+}
+
+impl FloodControl {
+    pub fn new(config: FloodConfig) -> Self {
+        FloodControl {
+            config,
+            connections: HashMap::new(),
+            addresses: HashMap::new(),
+            stats: FloodStats::default()
+        }
+    }
+
+    // New rates apply to connections and addresses that show up from now on. Strike settings apply straight away.
+    pub fn set_config(&mut self, config: FloodConfig) {
+        self.config = config;
+    }
+
+    pub fn add(&mut self, id: ConnId, ip: IpAddr) {
+        let config: &FloodConfig = &self.config;
+
+        self.connections.insert(id, Connection {
+            ip,
+            limiter: Limiter::new(config.messages_per_second, config.message_burst, config.bytes_per_second, config.byte_burst),
+            strikes: 0,
+            last_strike: Instant::now(),
+            muted_until: None
+        });
+
+        self.addresses.entry(ip)
+            .or_insert_with(|| Address {
+                limiter: Limiter::new(config.ip_messages_per_second, config.ip_message_burst, config.ip_bytes_per_second, config.ip_byte_burst),
+                connections: 0
+            })
+            .connections += 1;
+    }
+
+    pub fn remove(&mut self, id: ConnId) {
+        let ip: IpAddr = match self.connections.remove(&id) {
+            Some(connection) => connection.ip,
+            None => return
+        };
+
+        let is_last: bool = match self.addresses.get_mut(&ip) {
+            Some(address) => {
+                address.connections -= 1;
+                address.connections == 0
+            },
+            None => false
+        };
+
+        if is_last {
+            self.addresses.remove(&ip);
+        }
+    }
+
+    // Counts a packet of `bytes` bytes from `id` against its limits
+    pub fn check(&mut self, id: ConnId, bytes: usize) -> Verdict {
+        let now: Instant = Instant::now();
+        self.stats.packets += 1;
+        self.stats.bytes += bytes as u64;
+
+        let connection: &mut Connection = match self.connections.get_mut(&id) {
+            Some(connection) => connection,
+            None => return Verdict::Allow
+        };
+
+        let is_allowed: bool = connection.limiter.take(bytes, now)
+            && self.addresses.get_mut(&connection.ip).is_none_or(|address| address.limiter.take(bytes, now));
+
+        if is_allowed {
+            return Verdict::Allow;
+        }
+
+        self.stats.dropped += 1;
+
+        let decay_seconds: u64 = self.config.strike_decay_seconds.max(1);
+        let decayed: u64 = now.saturating_duration_since(connection.last_strike).as_secs() / decay_seconds;
+        connection.strikes = connection.strikes.saturating_sub(decayed.min(u32::MAX as u64) as u32) + 1;
+        connection.last_strike = now;
+
+        let is_muted: bool = connection.muted_until.is_some_and(|until| now < until);
+
+        if self.config.disconnect_after > 0 && connection.strikes >= self.config.disconnect_after {
+            self.stats.disconnects += 1;
+            Verdict::Disconnect
+        }
+        else if self.config.mute_after > 0 && connection.strikes >= self.config.mute_after && !is_muted {
+            let duration: Duration = Duration::from_secs(self.config.mute_seconds);
+            connection.muted_until = Some(now + duration);
+
+            self.stats.mutes += 1;
+            Verdict::Mute(duration)
+        }
+        else if connection.strikes == 1 {
+            self.stats.warnings += 1;
+            Verdict::Warn
+        }
+        else {
+            Verdict::Throttle
+        }
+    }
+
+    // How much longer `id` is muted for flooding, if it is
+    pub fn muted_for(&self, id: ConnId) -> Option<Duration> {
+        let until: Instant = self.connections.get(&id)?.muted_until?;
+        let remaining: Duration = until.saturating_duration_since(Instant::now());
+
+        if remaining > Duration::from_secs(0) {
+            Some(remaining)
+        }
+        else {
+            None
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde_json;
+
+// Most messages a client can ask for at once
+pub const MAX_HISTORY_PAGE: usize = 50;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Entry {
+    // Counts up from 1 within each channel
+    pub id: u64,
+    // Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub sender: String,
+    pub message: String,
+    #[serde(default)]
+    pub is_action: bool
+}
+
+// The messages of one channel, mirrored in an append-only log with one JSON entry per line
+struct Log {
+    entries: Vec<Entry>,
+    file: Option<File>
+}
+
+// Per-channel message history, kept in `<data_dir>/history/<channel>.log`.
+// A channel's log is read the first time it is needed and appended to from then on.
+pub struct History {
+    dir: PathBuf,
+    logs: HashMap<String, Log>
+}
+
+impl History {
+    pub fn open(data_dir: &Path) -> io::Result<History> {
+        let dir: PathBuf = data_dir.join("history");
+        fs::create_dir_all(&dir)?;
+
+        Ok(History {
+            dir,
+            logs: HashMap::new()
+        })
+    }
+
+    pub fn append(&mut self, channel: &str, sender: &str, message: &str, is_action: bool) {
+        let log: &mut Log = self.log(channel);
+
+        let entry = Entry {
+            id: log.entries.last().map_or(1, |last| last.id + 1),
+            timestamp: now(),
+            sender: String::from(sender),
+            message: String::from(message),
+            is_action
+        };
+
+        if let Some(ref mut file) = log.file {
+            let result = serde_json::to_string(&entry)
+                .map_err(io::Error::from)
+                .and_then(|line| writeln!(file, "{}", line));
+
+            if let Err(e) = result {
+                warn!("Failed to save a message in {}: {}", channel, e);
+            }
+        }
+
+        log.entries.push(entry);
+    }
+
+    // Up to `count` entries older than `before` (or the newest ones when `before` is 0), oldest first.
+    // Also says whether there are even older entries.
+    pub fn page(&mut self, channel: &str, before: u64, count: usize) -> (Vec<Entry>, bool) {
+        let entries: &[Entry] = &self.log(channel).entries;
+
+        let end: usize = if before == 0 {
+            entries.len()
+        }
+        else {
+            entries.iter().position(|entry| entry.id >= before).unwrap_or(entries.len())
+        };
+
+        let start: usize = end.saturating_sub(count.min(MAX_HISTORY_PAGE));
+        (entries[start..end].to_vec(), start > 0)
+    }
+
+    // Makes sure everything appended so far is on disk
+    pub fn sync(&self) {
+        for (channel, log) in &self.logs {
+            if let Some(Err(e)) = log.file.as_ref().map(|file| file.sync_all()) {
+                warn!("Failed to save the history of #{}: {}", channel, e);
+            }
+        }
+    }
+
+    fn log(&mut self, channel: &str) -> &mut Log {
+        let key: String = channel.trim_start_matches('#').to_ascii_lowercase();
+        let path: PathBuf = self.dir.join(format!("{}.log", key));
+
+        self.logs.entry(key).or_insert_with(|| Log::load(&path))
+    }
+}
+
+impl Log {
+    fn load(path: &Path) -> Log {
+        let mut entries: Vec<Entry> = Vec::new();
+        let text: String = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                warn!("Failed to read {}: {}", path.display(), e);
+                String::new()
+            }
+        };
+
+        for (number, line) in text.lines().enumerate() {
+            // A line cut short by a crash is skipped rather than losing the whole log
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => warn!("Skipping line {} of {}: {}", number + 1, path.display(), e)
+            }
+        }
+
+        let mut file: Option<File> = match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                warn!("History for {} won't be saved: {}", path.display(), e);
+                None
+            }
+        };
+
+        // Don't glue the next entry onto the end of a cut short line
+        if let Some(ref mut file) = file {
+            if !text.is_empty() && !text.ends_with('\n') {
+                let _ = writeln!(file);
+            }
+        }
+
+        Log {
+            entries,
+            file
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0)
+}
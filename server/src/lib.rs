@@ -0,0 +1,23 @@
+extern crate doosknet;
+pub extern crate rustychat_logger as logger;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
+extern crate serde_json;
+extern crate argon2;
+#[macro_use]
+extern crate log;
+
+pub mod config;
+pub mod nickname;
+pub mod channels;
+pub mod history;
+pub mod accounts;
+pub mod roles;
+pub mod bans;
+pub mod audit;
+pub mod flood;
+pub mod chat;
+pub mod commands;
+pub mod console;
@@ -1,173 +1,198 @@
-extern crate mio;
 extern crate doosknet;
-
-use std::collections::{HashMap, VecDeque};
-use std::io::{self, Read};
-use std::str;
-use mio::*;
-use mio::net::{TcpListener, TcpStream};
+extern crate signal_hook;
+#[macro_use]
+extern crate log;
+extern crate rustychat_server;
+
+use std::env;
+use std::io;
+use std::net::SocketAddr;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use signal_hook::consts::{SIGINT, SIGTERM};
 use doosknet::*;
+use rustychat_server::{config, logger};
+use rustychat_server::config::Config;
+use rustychat_server::chat::ChatServer;
+use rustychat_server::history::History;
+use rustychat_server::accounts::Accounts;
+use rustychat_server::bans::Bans;
+use rustychat_server::audit::Audit;
+use rustychat_server::console::Console;
+
+// Longest the main loop sleeps before checking timeouts
+const TICK: Duration = Duration::from_secs(1);
 
-static SERVER_USERNAME: &'static str = "SERVER";
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let config: Config = match Config::load(&args) {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            println!("{}", config::USAGE);
+            return;
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Run with --help for a list of options.");
+            process::exit(2);
+        }
+    };
 
-struct Connection {
-    token: Token,
-    socket: TcpStream,
-    is_disconnected: bool,
-    buffer: NetworkBuffer,
-    outgoing_packets: VecDeque<Packet>
-}
+    if let Err(e) = logger::init(&config.log_level, config.log_file.as_ref()) {
+        eprintln!("Failed to start logging: {}", e);
+    }
+
+    let server: Server = match start_server(&config) {
+        Ok(server) => server,
+        Err(e) => {
+            error!("Failed to start the server: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let history: History = match History::open(&config.data_dir) {
+        Ok(history) => history,
+        Err(e) => {
+            error!("Failed to open the message history in {}: {}", config.data_dir.display(), e);
+            process::exit(1);
+        }
+    };
 
-impl Connection {
-    pub fn new(token: Token, socket: TcpStream) -> Self {
-        Connection {
-            token,
-            socket,
-            is_disconnected: false,
-            buffer: NetworkBuffer::new(),
-            outgoing_packets: VecDeque::new()
+    let accounts: Accounts = match Accounts::open(&config.data_dir) {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            error!("Failed to load accounts from {}: {}", config.data_dir.display(), e);
+            process::exit(1);
+        }
+    };
+
+    let bans: Bans = match Bans::open(&config.data_dir) {
+        Ok(bans) => bans,
+        Err(e) => {
+            error!("Failed to load bans from {}: {}", config.data_dir.display(), e);
+            process::exit(1);
+        }
+    };
+
+    let audit: Audit = match Audit::open(&config.data_dir) {
+        Ok(audit) => audit,
+        Err(e) => {
+            error!("Failed to open the audit log in {}: {}", config.data_dir.display(), e);
+            process::exit(1);
+        }
+    };
+
+    let mut chat: ChatServer = ChatServer::new(server, config, history, accounts, bans, audit);
+
+    // Set by SIGINT or SIGTERM
+    let stop: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+    for &signal in &[SIGINT, SIGTERM] {
+        if let Err(e) = signal_hook::flag::register(signal, stop.clone()) {
+            warn!("Failed to handle signal {}: {}", signal, e);
         }
     }
-}
 
-fn main() {
-    // Setup the server socket
-    let addr = "0.0.0.0:7667".parse().unwrap();
-    let server = TcpListener::bind(&addr).unwrap();
-
-    println!("Server started on {}", addr);
-
-    // Create a poll instance
-    let poll = Poll::new().unwrap();
-
-    // Start listening for incoming connections
-    poll.register(&server, LOCAL_TOKEN, Ready::readable(), PollOpt::edge()).unwrap();
-
-    // Create storage for events
-    let mut events = Events::with_capacity(1024);
-
-    let mut next_token_index: usize = 0;
-    let mut connections: HashMap<Token, Connection> = HashMap::new();
-
-    let mut incoming_packets: VecDeque<Packet> = VecDeque::new();
-
-    loop {
-        poll.poll(&mut events, None).unwrap();
-
-        for event in events.iter() {
-            match event.token() {
-                LOCAL_TOKEN => {
-                    match server.accept() {
-                        Ok((socket, addr)) => {
-                            println!("New connection from {}", addr);
-
-                            next_token_index += 1;
-                            let token = Token(next_token_index);
-
-                            poll.register(&socket, token, Ready::readable() | Ready::writable(), PollOpt::edge()).unwrap();
-
-                            send_all_msg("A client entered the room.", &mut connections);
-
-                            let mut connection = Connection::new(token, socket);
-                            send_msg("Welcome to Rustychat!", &mut connection);
-                            connections.insert(token, connection);
-
-                            println!("There are now {} clients connected.", connections.len());
-                        },
-                        Err(e) => println!("{}", e)
-                    }
-                },
-                token => {
-                    // Get the connection
-                    let conn: &mut Connection = connections.get_mut(&token).unwrap();
-
-                    if event.readiness().is_readable() {
-                        loop {
-                            // Read until there are no more incoming bytes
-                            let buffer = &mut conn.buffer.data[conn.buffer.offset..];
-                            match conn.socket.read(buffer) {
-                                Ok(0) => {
-                                    // Socket is closed
-                                    println!("Client {:?} has disconnected!", token);
-                                    conn.is_disconnected = true;
-
-                                    break;
-                                },
-                                Ok(read_bytes) => {
-                                    conn.buffer.offset += read_bytes;
-                                    println!("Read {} bytes from client {:?}", read_bytes, token);
-                                },
-                                Err(e) => {
-                                    if e.kind() == io::ErrorKind::WouldBlock {
-                                        // Socket is not ready anymore, stop reading
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    else if event.readiness().is_writable() {
-                        // Send all outgoing packets
-                        while let Some(packet) = conn.outgoing_packets.pop_front() {
-                            let data = serialize_packet(packet);
-                            match send_bytes(&mut conn.socket, &data) {
-                                Ok(sent_bytes) => {
-                                    println!("Sent {} bytes to client {:?}", sent_bytes, conn.token);
-                                },
-                                Err(e) => {
-                                    eprintln!("send_bytes() failed with error {:?}", e);
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                }
+    let console: Option<Console> = if chat.config.console {
+        start_console(&mut chat, &args, &stop)
+    }
+    else {
+        None
+    };
+
+    while !stop.load(Ordering::SeqCst) {
+        let events: Vec<NetworkEvent> = match chat.server.poll(Some(TICK)) {
+            Ok(events) => events,
+            // A signal arrived while we were waiting
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::Interrupted => Vec::new(),
+            Err(e) => {
+                error!("Polling failed: {}", e);
+                process::exit(1);
             }
+        };
+
+        for event in events {
+            chat.handle_event(event);
         }
 
-        // Remove any disconnected clients
-        connections = connections.into_iter()
-            .filter(|&(_, ref v)| !v.is_disconnected)
-            .collect();
+        if let Some(ref console) = console {
+            console.run_pending(&mut chat);
+        }
 
-        // Process incoming bytes to create packets
-        for (_, connection) in &mut connections {
-            poll.reregister(&connection.socket, connection.token, Ready::readable() | Ready::writable(), PollOpt::edge()).unwrap();
+        chat.tick();
+    }
 
-            if connection.buffer.offset == 0 {
-                continue;
-            }
+    process::exit(shut_down(&mut chat, &stop));
+}
+
+// Says goodbye to everyone and waits for their connections to drain, for up to shutdown_timeout_seconds.
+// A second signal stops the wait. Returns the exit status: 0 if everything was delivered, 1 if not.
+fn shut_down(chat: &mut ChatServer, stop: &AtomicBool) -> i32 {
+    chat.shut_down("The server is shutting down.");
+    stop.store(false, Ordering::SeqCst);
 
-            while let Some(packet) = deserialize_packet(&mut connection.buffer) {
-                incoming_packets.push_back(packet);
+    let deadline: Instant = Instant::now() + Duration::from_secs(chat.config.shutdown_timeout_seconds);
+
+    while chat.server.connection_count() > 0 {
+        let now: Instant = Instant::now();
+        if now >= deadline || stop.load(Ordering::SeqCst) {
+            warn!("Gave up on {} connections that were still sending", chat.server.connection_count());
+            return 1;
+        }
+
+        // Anything they send now is ignored; their connections are closing
+        match chat.server.poll(Some((deadline - now).min(TICK))) {
+            Ok(_) => (),
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => {
+                error!("Polling failed while shutting down: {}", e);
+                return 1;
             }
         }
+    }
 
-        // Handle packets
-        while let Some(packet) = incoming_packets.pop_front() {
-            println!("> {}", packet.message);
+    info!("Shut down cleanly");
+    0
+}
 
-            send_all(packet, &mut connections);
+// The server keeps running without a console if it can't have one
+fn start_console(chat: &mut ChatServer, args: &[String], stop: &Arc<AtomicBool>) -> Option<Console> {
+    let result: Result<Console, String> = chat.server.waker()
+        .map_err(|e| e.to_string())
+        .and_then(|waker| Console::start(args.to_vec(), stop.clone(), waker).map_err(|e| e.to_string()));
+
+    match result {
+        Ok(console) => {
+            info!("Type help for a list of console commands");
+            Some(console)
+        },
+        Err(e) => {
+            warn!("Failed to start the console: {}", e);
+            None
         }
     }
 }
 
-fn send(packet: Packet, connection: &mut Connection) {
-    connection.outgoing_packets.push_back(packet.clone());
-}
+fn start_server(config: &Config) -> Result<Server, Error> {
+    let tls_config: Option<Arc<tls::ServerConfig>> = match config.tls {
+        Some(ref tls_files) => {
+            let tls_config = tls::load_server_config(&tls_files.cert, &tls_files.key)?;
+            info!("TLS enabled, certificate fingerprint {}", tls::certificate_fingerprint(&tls_files.cert)?);
 
-fn send_all(packet: Packet, connections: &mut HashMap<Token, Connection>) {
-    for (_, connection) in connections {
-        connection.outgoing_packets.push_back(packet.clone());
+            Some(tls_config)
+        },
+        None => None
+    };
+
+    let mut server: Server = Server::bind_all(&config.bind, tls_config)?;
+    server.set_event_capacity(config.event_capacity);
+    server.set_heartbeat(config.heartbeat());
+
+    let addrs: Vec<SocketAddr> = server.local_addrs()?;
+    for addr in addrs {
+        info!("{} started on {}", config.server_name, addr);
     }
-}
 
-fn send_msg(message: &str, connection: &mut Connection) {
-    let packet: Packet = Packet::new(SERVER_USERNAME, message);
-    send(packet, connection);
+    Ok(server)
 }
-
-fn send_all_msg(message: &str, connections: &mut HashMap<Token, Connection>) {
-    let packet: Packet = Packet::new(SERVER_USERNAME, message);
-    send_all(packet, connections);
-}
\ No newline at end of file
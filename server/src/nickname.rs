@@ -0,0 +1,34 @@
+pub const MAX_NICKNAME_LENGTH: usize = 16;
+
+// Names nobody may take, so that server and operator messages can't be faked. Compared case-insensitively.
+pub static RESERVED_NICKNAMES: &[&str] = &["server", "admin", "console", "system", "operator", "root"];
+
+// Checks that a nickname is well formed and not reserved. Whether it is already taken is up to the caller.
+pub fn validate(nickname: &str) -> Result<(), String> {
+    if nickname.is_empty() || nickname.len() > MAX_NICKNAME_LENGTH {
+        return Err(format!("Nicknames must be 1 to {} characters long.", MAX_NICKNAME_LENGTH));
+    }
+
+    if !nickname.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return Err(String::from("Nicknames must start with a letter."));
+    }
+
+    if !nickname.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(String::from("Nicknames may only contain letters, digits, '_' and '-'."));
+    }
+
+    if is_reserved(nickname) {
+        return Err(format!("The nickname {} is reserved.", nickname));
+    }
+
+    Ok(())
+}
+
+pub fn is_reserved(nickname: &str) -> bool {
+    RESERVED_NICKNAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(nickname))
+}
+
+// Nicknames are unique regardless of case, so "Bob" and "bob" can't both be online
+pub fn same(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
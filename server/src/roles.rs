@@ -0,0 +1,54 @@
+use std::fmt;
+use std::str::FromStr;
+
+// What someone may do, lowest first. A server role applies in every channel;
+// within a channel, whichever of the server and channel roles is higher wins.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    // May talk in moderated channels
+    Voice,
+    // May kick, mute, voice and change the topic
+    Op,
+    // May also hand out and take away op
+    Owner
+}
+
+impl Role {
+    // Shown in front of nicknames in /who
+    pub fn prefix(&self) -> &'static str {
+        match *self {
+            Role::User => "",
+            Role::Voice => "+",
+            Role::Op => "@",
+            Role::Owner => "~"
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Role::User => write!(f, "user"),
+            Role::Voice => write!(f, "voice"),
+            Role::Op => write!(f, "op"),
+            Role::Owner => write!(f, "owner")
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Role, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "user" => Ok(Role::User),
+            "voice" => Ok(Role::Voice),
+            "op" => Ok(Role::Op),
+            "owner" => Ok(Role::Owner),
+            _ => Err(format!("Unknown role {}. Roles are owner, op, voice and user.", s))
+        }
+    }
+}
@@ -0,0 +1,43 @@
+extern crate rustychat_server;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use rustychat_server::accounts::Accounts;
+
+fn data_dir(name: &str) -> PathBuf {
+    let path: PathBuf = env::temp_dir().join(format!("rustychat-test-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
+#[test]
+fn accounts_survive_a_restart() {
+    let dir: PathBuf = data_dir("accounts-reopen");
+    Accounts::open(&dir).unwrap().register("alice", "hunter22").unwrap();
+
+    let accounts: Accounts = Accounts::open(&dir).unwrap();
+    assert!(accounts.verify("Alice", "hunter22"));
+    assert!(!accounts.verify("alice", "hunter23"));
+    assert!(!dir.join("accounts.json.tmp").exists());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[cfg(unix)]
+#[test]
+fn only_the_server_can_read_the_password_hashes() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir: PathBuf = data_dir("accounts-mode");
+    let mut accounts: Accounts = Accounts::open(&dir).unwrap();
+    accounts.register("alice", "hunter22").unwrap();
+    accounts.register("bob", "hunter22").unwrap();
+
+    let mode: u32 = fs::metadata(dir.join("accounts.json")).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,73 @@
+extern crate rustychat_server;
+
+use std::net::IpAddr;
+use rustychat_server::bans::{BanTarget, Network};
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+fn network(s: &str) -> Network {
+    s.parse().unwrap()
+}
+
+#[test]
+fn networks_contain_the_addresses_under_their_prefix() {
+    let lan: Network = network("192.168.1.0/24");
+    assert!(lan.contains(ip("192.168.1.0")));
+    assert!(lan.contains(ip("192.168.1.255")));
+    assert!(!lan.contains(ip("192.168.2.1")));
+
+    // A plain address is a range of one, and /0 is everything
+    assert!(network("10.0.0.1").contains(ip("10.0.0.1")));
+    assert!(!network("10.0.0.1").contains(ip("10.0.0.2")));
+    assert!(network("0.0.0.0/0").contains(ip("203.0.113.7")));
+
+    let v6: Network = network("2001:db8::/32");
+    assert!(v6.contains(ip("2001:db8:ffff::1")));
+    assert!(!v6.contains(ip("2001:db9::1")));
+    assert!(network("::/0").contains(ip("::1")));
+
+    // IPv4 ranges never match IPv6 addresses, or the other way around
+    assert!(!network("0.0.0.0/0").contains(ip("::1")));
+    assert!(!network("::/0").contains(ip("127.0.0.1")));
+}
+
+#[test]
+fn networks_are_written_the_way_they_are_parsed() {
+    assert_eq!(network("10.1.0.0/16").to_string(), "10.1.0.0/16");
+    assert_eq!(network("10.1.2.3/32").to_string(), "10.1.2.3");
+    assert_eq!(network("::1").to_string(), "::1");
+
+    for invalid in &["", "10.0.0", "10.0.0.0/33", "::/129", "10.0.0.0/", "10.0.0.0/x", "example.com"] {
+        assert!(invalid.parse::<Network>().is_err(), "{} parsed", invalid);
+    }
+}
+
+#[test]
+fn ban_targets_parse_from_their_prefix() {
+    assert_eq!("nick:Bob".parse(), Ok(BanTarget::Nickname(String::from("Bob"))));
+    assert_eq!("account:bob".parse(), Ok(BanTarget::Account(String::from("bob"))));
+    assert_eq!("ip:10.0.0.0/8".parse(), Ok(BanTarget::Address(network("10.0.0.0/8"))));
+
+    for invalid in &["bob", "nick:", "account:", "ip:bob", "host:example.com"] {
+        assert!(invalid.parse::<BanTarget>().is_err(), "{} parsed", invalid);
+    }
+
+    let target: BanTarget = "ip:10.0.0.0/8".parse().unwrap();
+    assert_eq!(target.to_string().parse(), Ok(target));
+}
+
+#[test]
+fn ban_targets_match_names_regardless_of_case() {
+    let home: IpAddr = ip("192.0.2.1");
+
+    assert!(BanTarget::Nickname(String::from("Bob")).matches(Some("bob"), None, home));
+    assert!(!BanTarget::Nickname(String::from("Bob")).matches(Some("bobby"), None, home));
+    assert!(!BanTarget::Nickname(String::from("Bob")).matches(None, Some("bob"), home));
+    assert!(BanTarget::Account(String::from("bob")).matches(Some("robert"), Some("BOB"), home));
+    assert!(BanTarget::Address(network("192.0.2.0/24")).matches(None, None, home));
+
+    assert!(BanTarget::Nickname(String::from("Bob")).same(&BanTarget::Nickname(String::from("BOB"))));
+    assert!(!BanTarget::Nickname(String::from("bob")).same(&BanTarget::Account(String::from("bob"))));
+}
@@ -0,0 +1,46 @@
+extern crate rustychat_server;
+
+use std::time::{Duration, Instant};
+use rustychat_server::channels::{Channel, Mute};
+
+fn mute(nickname: &str, account: Option<&str>, until: Option<Instant>) -> Mute {
+    Mute { nickname: String::from(nickname), account: account.map(String::from), until }
+}
+
+#[test]
+fn mutes_follow_the_nickname_or_account() {
+    let mut channel = Channel::new("#rust");
+    channel.mute(mute("Bob", Some("robert"), None));
+
+    assert!(channel.is_muted("bob", None));
+    assert!(channel.is_muted("bobby", Some("Robert")));
+    assert!(!channel.is_muted("alice", None));
+    assert!(!channel.is_muted("alice", Some("alice")));
+
+    // Muting again replaces the mute rather than adding a second one
+    channel.mute(mute("bob", None, None));
+    assert_eq!(channel.muted.len(), 1);
+
+    assert!(channel.unmute("BOB", None));
+    assert!(!channel.is_muted("bob", None));
+    assert!(!channel.unmute("bob", None));
+}
+
+#[test]
+fn mutes_run_out_and_are_pruned() {
+    let now: Instant = Instant::now();
+    let mut channel = Channel::new("#rust");
+    channel.mute(mute("bob", None, Some(now)));
+    channel.mute(mute("carol", None, Some(now + Duration::from_secs(60))));
+    channel.mute(mute("dave", None, None));
+
+    assert!(!channel.is_muted("bob", None));
+    assert!(channel.is_muted("carol", None));
+
+    // Nothing is left to lift once a mute has run out
+    assert!(!channel.unmute("bob", None));
+
+    channel.prune_mutes(now + Duration::from_secs(60));
+    let left: Vec<&str> = channel.muted.iter().map(|mute| mute.nickname.as_str()).collect();
+    assert_eq!(left, vec!["dave"]);
+}
@@ -0,0 +1,308 @@
+extern crate doosknet;
+extern crate rustychat_server;
+
+mod harness;
+
+use doosknet::*;
+use rustychat_server::config::Config;
+use harness::Harness;
+
+fn join(channel: &str, username: &str) -> Message {
+    Message::Join { channel: String::from(channel), username: String::from(username) }
+}
+
+fn leave(channel: &str, username: &str) -> Message {
+    Message::Leave { channel: String::from(channel), username: String::from(username) }
+}
+
+// Just the chat lines, as "sender: message"
+fn lines(received: &[Message]) -> Vec<String> {
+    received.iter()
+        .filter_map(|packet| match *packet {
+            Message::Chat { ref sender, ref message, .. } => Some(format!("{}: {}", sender, message)),
+            _ => None
+        })
+        .collect()
+}
+
+#[test]
+fn new_users_are_welcomed_into_the_default_channel() {
+    let mut harness = Harness::new("welcome");
+    let alice: usize = harness.connect();
+    assert!(harness.received(alice).is_empty());
+
+    harness.send(alice, Message::hello("alice"));
+
+    let config = Config::default();
+    assert_eq!(harness.received(alice), vec![
+        Message::welcome("alice", vec![String::from("chat")], &config.server_name, &config.motd),
+        join("#general", "alice"),
+        Message::Joined { channel: String::from("#general"), topic: String::new() },
+        Message::HistoryEnd { channel: String::from("#general"), has_more: false }
+    ]);
+}
+
+#[test]
+fn everyone_sees_messages_in_the_order_they_were_sent() {
+    let mut harness = Harness::new("ordering");
+    let alice: usize = harness.join("alice");
+    let bob: usize = harness.join("bob");
+    let carol: usize = harness.join("carol");
+    harness.received(alice);
+    harness.received(bob);
+
+    harness.say(alice, "#general", "one");
+    harness.say(bob, "#general", "two");
+    harness.say(carol, "#general", "three");
+    harness.say(alice, "#general", "four");
+
+    let expected: Vec<&str> = vec!["alice: one", "bob: two", "carol: three", "alice: four"];
+    for &client in &[alice, bob, carol] {
+        assert_eq!(lines(&harness.received(client)), expected);
+    }
+
+    // Someone arriving later is caught up in the same order
+    let dave: usize = harness.connect();
+    harness.send(dave, Message::hello("dave"));
+    let replayed: Vec<String> = harness.received(dave).iter()
+        .filter_map(|packet| match *packet {
+            Message::HistoryEntry { ref sender, ref message, .. } => Some(format!("{}: {}", sender, message)),
+            _ => None
+        })
+        .collect();
+
+    assert_eq!(replayed, expected);
+}
+
+#[test]
+fn joining_and_leaving_channels_is_announced() {
+    let mut harness = Harness::new("join-leave");
+    let alice: usize = harness.join("alice");
+    let bob: usize = harness.join("bob");
+    assert_eq!(harness.received(alice), vec![join("#general", "bob")]);
+
+    harness.say(bob, "#general", "/join #rust");
+    assert_eq!(harness.received(bob)[0], join("#rust", "bob"));
+    assert!(harness.received(alice).is_empty());
+
+    harness.say(alice, "#general", "/join #rust");
+    assert_eq!(harness.received(bob), vec![join("#rust", "alice")]);
+    harness.received(alice);
+
+    harness.say(bob, "#rust", "/part");
+    assert_eq!(harness.received(alice), vec![leave("#rust", "bob")]);
+    assert_eq!(harness.received(bob), vec![leave("#rust", "bob")]);
+
+    // Lines in one channel stay there
+    harness.say(alice, "#rust", "crabs");
+    assert_eq!(lines(&harness.received(alice)), vec!["alice: crabs"]);
+    assert!(harness.received(bob).is_empty());
+}
+
+#[test]
+fn clients_that_go_away_leave_their_channels() {
+    let mut harness = Harness::new("drop");
+    let alice: usize = harness.join("alice");
+    let bob: usize = harness.join("bob");
+    harness.say(bob, "#general", "/join #rust");
+    harness.say(alice, "#general", "/join #rust");
+    harness.received(alice);
+
+    harness.drop_client(bob);
+
+    let mut received: Vec<Message> = harness.received(alice);
+    received.sort_by_key(|packet| format!("{:?}", packet));
+    assert_eq!(received, vec![leave("#general", "bob"), leave("#rust", "bob")]);
+    assert_eq!(harness.chat.server.connection_count(), 1);
+
+    // The nickname is free again
+    let bob_again: usize = harness.join("bob");
+    assert!(!harness.is_disconnected(bob_again));
+    assert_eq!(harness.received(alice), vec![join("#general", "bob")]);
+}
+
+#[test]
+fn misbehaving_clients_are_disconnected() {
+    let mut harness = Harness::new("reject");
+    let alice: usize = harness.join("alice");
+    let stranger: usize = harness.connect();
+
+    harness.say(stranger, "#general", "hi");
+
+    assert_eq!(harness.received(stranger), vec![Message::rejected("Expected a Hello packet before any other traffic.")]);
+    assert!(harness.is_disconnected(stranger));
+    assert!(!harness.is_disconnected(alice));
+    assert_eq!(harness.chat.server.connection_count(), 1);
+
+    // Nobody was told, since the stranger never joined
+    assert!(harness.received(alice).is_empty());
+}
+
+#[test]
+fn taken_nicknames_can_be_retried() {
+    let mut harness = Harness::new("nickname");
+    harness.join("alice");
+    let other: usize = harness.connect();
+
+    harness.send(other, Message::hello("alice"));
+    assert!(matches!(harness.received(other)[..], [Message::NicknameRejected { .. }]));
+    assert!(!harness.is_disconnected(other));
+
+    harness.send(other, Message::hello("alicia"));
+    assert!(matches!(harness.received(other)[0], Message::Welcome { ref username, .. } if username == "alicia"));
+    assert!(harness.chat.find_user("alicia").is_some());
+}
+
+#[test]
+fn shutting_down_says_goodbye_to_everyone() {
+    let mut harness = Harness::new("shutdown");
+    let alice: usize = harness.join("alice");
+    let bob: usize = harness.join("bob");
+    harness.received(alice);
+
+    harness.chat.shut_down("Back soon.");
+    harness.settle();
+
+    for &client in &[alice, bob] {
+        assert_eq!(harness.received(client), vec![Message::notice("Back soon.")]);
+        assert!(harness.is_disconnected(client));
+    }
+
+    assert_eq!(harness.chat.server.connection_count(), 0);
+}
+
+#[test]
+fn a_full_server_turns_clients_away() {
+    let config = Config { max_connections: 1, ..Config::default() };
+    let mut harness = Harness::with_config("full", config);
+
+    let alice: usize = harness.join("alice");
+    let bob: usize = harness.connect();
+
+    assert_eq!(harness.received(bob), vec![Message::rejected("The server is full.")]);
+    assert!(harness.is_disconnected(bob));
+
+    // Which frees the slot up again
+    harness.drop_client(alice);
+    let carol: usize = harness.join("carol");
+    assert!(!harness.is_disconnected(carol));
+    assert!(harness.chat.find_user("carol").is_some());
+}
+
+#[test]
+fn clients_on_another_version_are_told_why() {
+    let mut harness = Harness::new("version");
+    let old: usize = harness.connect();
+
+    harness.send(old, Message::Hello { version: PROTOCOL_VERSION - 1, capabilities: Vec::new(), username: String::from("alice") });
+
+    assert!(matches!(harness.received(old)[..], [Message::Rejected { ref reason }] if reason.contains("version")));
+    assert!(harness.is_disconnected(old));
+}
+
+#[test]
+fn guessing_passwords_with_login_is_cut_short() {
+    let mut harness = Harness::new("login-limit");
+    let alice: usize = harness.join("alice");
+    harness.say(alice, "#general", "/register hunter22");
+    harness.drop_client(alice);
+
+    let guesser: usize = harness.join("mallory");
+    harness.say(guesser, "#general", "/login alice wrong1");
+    harness.say(guesser, "#general", "/login alice wrong2");
+    assert_eq!(harness.received(guesser), vec![Message::error("Wrong password."), Message::error("Wrong password.")]);
+    assert!(!harness.is_disconnected(guesser));
+
+    harness.say(guesser, "#general", "/login alice wrong3");
+    assert_eq!(harness.received(guesser).last(), Some(&Message::rejected("Too many wrong passwords.")));
+    assert!(harness.is_disconnected(guesser));
+}
+
+#[test]
+fn messages_too_long_to_relay_are_not_stored() {
+    let mut harness = Harness::new("too-long");
+    let alice: usize = harness.join("alice");
+    let bob: usize = harness.join("bob");
+    harness.received(alice);
+
+    // Fits with the empty sender the client sends, but not once the server fills in "alice"
+    let line: String = "x".repeat(215);
+    assert!(serialize_packet(&Message::chat("#general", "", &line)).is_ok());
+
+    harness.say(alice, "#general", &line);
+    assert_eq!(harness.received(alice), vec![Message::error("That message is too long to send.")]);
+    assert!(harness.received(bob).is_empty());
+
+    harness.say(alice, "#general", "/me waves");
+    let carol: usize = harness.connect();
+    harness.send(carol, Message::hello("carol"));
+
+    let replayed: Vec<Message> = harness.received(carol).into_iter()
+        .filter(|packet| matches!(*packet, Message::HistoryEntry { .. }))
+        .collect();
+    assert!(matches!(replayed[..], [Message::HistoryEntry { ref message, is_action: true, .. }] if message == "waves"));
+}
+
+#[test]
+fn reconnecting_does_not_lift_a_mute() {
+    let mut harness = Harness::new("mute-reconnect");
+    let alice: usize = harness.join("alice");
+    let bob: usize = harness.join("bob");
+    harness.say(alice, "#general", "/mute bob");
+    harness.received(alice);
+
+    harness.drop_client(bob);
+    let bob: usize = harness.join("bob");
+    harness.say(bob, "#general", "hello?");
+    assert_eq!(harness.received(bob), vec![Message::error("You are muted in #general.")]);
+
+    // And it can be lifted while they are away
+    harness.drop_client(bob);
+    harness.say(alice, "#general", "/unmute bob");
+    let bob: usize = harness.join("bob");
+    harness.say(bob, "#general", "hello");
+    assert_eq!(lines(&harness.received(alice)), vec!["bob: hello"]);
+}
+
+#[test]
+fn hundreds_of_clients_each_get_their_own_address() {
+    let config = Config { max_connections: 300, max_connections_per_ip: 1, ..Config::default() };
+    let mut harness = Harness::with_config("many", config);
+
+    let clients: Vec<usize> = (0..300).map(|_| harness.connect()).collect();
+    assert!(clients.iter().all(|&client| !harness.is_disconnected(client)));
+    assert_eq!(harness.chat.server.connection_count(), 300);
+}
+
+#[test]
+fn muted_users_cannot_shake_off_the_mute() {
+    let mut harness = Harness::new("mute-escape");
+    let alice: usize = harness.join("alice");
+    let bob: usize = harness.join("bob");
+    harness.say(alice, "#general", "/join #rust");
+    harness.say(bob, "#general", "/join #rust");
+    harness.say(alice, "#rust", "/mute bob");
+    harness.received(alice);
+    harness.received(bob);
+
+    // Not with a new nickname
+    harness.say(bob, "#general", "/nick bobby");
+    assert_eq!(harness.received(bob), vec![Message::error("You can't change your nickname while you are muted in #rust.")]);
+    assert!(harness.chat.find_user("bob").is_some());
+
+    // Nor by having the channel to themselves
+    harness.say(alice, "#rust", "/part");
+    harness.say(bob, "#rust", "/part");
+    harness.say(bob, "#general", "/join #rust");
+    harness.received(bob);
+    harness.say(bob, "#rust", "free again");
+    harness.say(bob, "#rust", "/unmute bob");
+    assert_eq!(harness.received(bob), vec![
+        Message::error("You are muted in #rust."),
+        Message::error("You don't have permission to use /unmute.")
+    ]);
+
+    // Other channels are still fine
+    harness.say(bob, "#general", "hello");
+    assert_eq!(lines(&harness.received(alice)), vec!["bob: hello"]);
+}
@@ -0,0 +1,39 @@
+extern crate doosknet;
+extern crate rustychat_server;
+
+use doosknet::*;
+use rustychat_server::config::{self, Config};
+use rustychat_server::nickname::MAX_NICKNAME_LENGTH;
+
+fn with_motd(server_name: &str, motd: &str) -> Config {
+    Config {
+        server_name: String::from(server_name),
+        motd: String::from(motd),
+        ..Config::default()
+    }
+}
+
+#[test]
+fn the_defaults_are_valid() {
+    assert!(Config::default().validate().is_ok());
+}
+
+#[test]
+fn motds_too_long_for_a_welcome_are_refused() {
+    assert!(with_motd("Rustychat", &"m".repeat(200)).validate().is_err());
+
+    let server_name: String = "s".repeat(32);
+    let max: usize = config::max_motd_length(&server_name);
+    assert!(with_motd(&server_name, &"m".repeat(max + 1)).validate().is_err());
+    assert!(with_motd(&server_name, &"m".repeat(max)).validate().is_ok());
+}
+
+#[test]
+fn the_longest_motd_still_reaches_every_client() {
+    let server_name: String = "s".repeat(32);
+    let motd: String = "m".repeat(config::max_motd_length(&server_name));
+    let capabilities: Vec<String> = CAPABILITIES.iter().map(|c| String::from(*c)).collect();
+
+    let welcome = Message::welcome(&"n".repeat(MAX_NICKNAME_LENGTH), capabilities, &server_name, &motd);
+    assert!(serialize_packet(&welcome).is_ok());
+}
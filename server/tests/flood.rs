@@ -0,0 +1,113 @@
+extern crate doosknet;
+extern crate rustychat_server;
+
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use doosknet::ConnId;
+use rustychat_server::config::FloodConfig;
+use rustychat_server::flood::{FloodControl, TokenBucket, Verdict};
+
+// One message allowed, and then almost nothing for the length of a test
+fn strict() -> FloodConfig {
+    FloodConfig {
+        messages_per_second: 0.001,
+        message_burst: 1,
+        mute_after: 3,
+        mute_seconds: 60,
+        disconnect_after: 5,
+        strike_decay_seconds: 3600,
+        ..FloodConfig::default()
+    }
+}
+
+fn home() -> IpAddr {
+    "192.0.2.1".parse().unwrap()
+}
+
+#[test]
+fn buckets_refill_over_time_up_to_their_capacity() {
+    let start: Instant = Instant::now();
+    let mut bucket = TokenBucket::new(2.0, 4.0);
+
+    assert!(bucket.take(4.0, start));
+    assert!(!bucket.take(1.0, start));
+
+    // Two tokens a second
+    assert!(!bucket.take(2.0, start + Duration::from_millis(500)));
+    assert!(bucket.take(2.0, start + Duration::from_millis(1000)));
+
+    // A long wait only fills it back up to capacity
+    let later: Instant = start + Duration::from_secs(60);
+    assert!(bucket.take(4.0, later));
+    assert!(!bucket.take(1.0, later));
+}
+
+#[test]
+fn a_rate_of_zero_never_runs_out() {
+    let now: Instant = Instant::now();
+    let mut bucket = TokenBucket::new(0.0, 0.0);
+
+    for _ in 0..1000 {
+        assert!(bucket.take(1000.0, now));
+    }
+}
+
+#[test]
+fn flooding_escalates_from_a_warning_to_a_mute_to_a_disconnect() {
+    let mut flood = FloodControl::new(strict());
+    let id = ConnId(1);
+    flood.add(id, home());
+
+    let verdicts: Vec<Verdict> = (0..6).map(|_| flood.check(id, 10)).collect();
+    assert_eq!(verdicts, vec![
+        Verdict::Allow,
+        Verdict::Warn,
+        Verdict::Throttle,
+        Verdict::Mute(Duration::from_secs(60)),
+        Verdict::Throttle,
+        Verdict::Disconnect
+    ]);
+
+    assert!(flood.muted_for(id).is_some_and(|remaining| remaining <= Duration::from_secs(60)));
+    assert_eq!(flood.stats.packets, 6);
+    assert_eq!(flood.stats.bytes, 60);
+    assert_eq!(flood.stats.dropped, 5);
+    assert_eq!((flood.stats.warnings, flood.stats.mutes, flood.stats.disconnects), (1, 1, 1));
+}
+
+#[test]
+fn connections_from_one_address_share_its_limits() {
+    let config = FloodConfig {
+        ip_messages_per_second: 0.001,
+        ip_message_burst: 2,
+        ..FloodConfig::default()
+    };
+    let mut flood = FloodControl::new(config);
+    let (first, second, elsewhere) = (ConnId(1), ConnId(2), ConnId(3));
+    flood.add(first, home());
+    flood.add(second, home());
+    flood.add(elsewhere, "198.51.100.1".parse().unwrap());
+
+    assert_eq!(flood.check(first, 10), Verdict::Allow);
+    assert_eq!(flood.check(second, 10), Verdict::Allow);
+    assert_eq!(flood.check(first, 10), Verdict::Warn);
+    assert_eq!(flood.check(elsewhere, 10), Verdict::Allow);
+
+    // The address starts afresh once everyone from it has gone
+    flood.remove(first);
+    flood.remove(second);
+    flood.add(first, home());
+    assert_eq!(flood.check(first, 10), Verdict::Allow);
+}
+
+#[test]
+fn bytes_count_as_well_as_messages() {
+    let config = FloodConfig { bytes_per_second: 1, byte_burst: 100, ..strict() };
+    let mut flood = FloodControl::new(FloodConfig { messages_per_second: 0.0, ..config });
+    let id = ConnId(1);
+    flood.add(id, home());
+
+    assert_eq!(flood.check(id, 60), Verdict::Allow);
+    assert_eq!(flood.check(id, 60), Verdict::Warn);
+    assert_eq!(flood.check(id, 30), Verdict::Allow);
+}
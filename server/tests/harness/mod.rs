@@ -0,0 +1,166 @@
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+use doosknet::*;
+use rustychat_server::config::Config;
+use rustychat_server::chat::ChatServer;
+use rustychat_server::history::History;
+use rustychat_server::accounts::Accounts;
+use rustychat_server::bans::Bans;
+use rustychat_server::audit::Audit;
+
+// Rounds of polling settle() allows before deciding the chat will never go quiet
+const MAX_ROUNDS: usize = 100;
+
+// One simulated client, connected to the harness over a pipe
+struct SimClient {
+    // None once the test has dropped it
+    client: Option<Client>,
+    received: Vec<Message>,
+    is_disconnected: bool
+}
+
+// The chat server and any number of clients, all in this process. Nothing listens on a socket and nothing
+// waits on a clock: every poll returns straight away, so what happens depends only on what the test does.
+pub struct Harness {
+    pub chat: ChatServer,
+    clients: Vec<SimClient>,
+    data_dir: PathBuf
+}
+
+impl Harness {
+    // A server with the default settings. `name` keeps each test's data directory apart.
+    pub fn new(name: &str) -> Harness {
+        Harness::with_config(name, Config::default())
+    }
+
+    pub fn with_config(name: &str, mut config: Config) -> Harness {
+        let data_dir: PathBuf = env::temp_dir().join(format!("rustychat-test-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&data_dir);
+
+        config.console = false;
+        config.data_dir = data_dir.clone();
+
+        let chat = ChatServer::new(
+            Server::unbound().unwrap(),
+            config,
+            History::open(&data_dir).unwrap(),
+            Accounts::open(&data_dir).unwrap(),
+            Bans::open(&data_dir).unwrap(),
+            Audit::open(&data_dir).unwrap()
+        );
+
+        Harness {
+            chat,
+            clients: Vec::new(),
+            data_dir
+        }
+    }
+
+    // A new client that hasn't said anything yet. Each one comes from its own address.
+    pub fn connect(&mut self) -> usize {
+        let index: usize = self.clients.len();
+        // 10.0.0.1, 10.0.0.2, ... carrying over into the higher octets, so there are millions to go around
+        let offset: u32 = u32::try_from(index + 1).ok().filter(|offset| *offset < 1 << 24).expect("out of addresses in 10.0.0.0/8");
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::from(u32::from(Ipv4Addr::new(10, 0, 0, 0)) + offset)), 40000);
+
+        let client: Client = self.chat.server.connect_pipe(&addr).unwrap();
+        self.clients.push(SimClient { client: Some(client), received: Vec::new(), is_disconnected: false });
+        self.settle();
+
+        index
+    }
+
+    // A new client that has said hello as `nickname`, with everything it was sent on the way in thrown away
+    pub fn join(&mut self, nickname: &str) -> usize {
+        let index: usize = self.connect();
+        self.send(index, Message::hello(nickname));
+        self.received(index);
+
+        index
+    }
+
+    // Sends `packet` from a client and lets everyone react to it
+    pub fn send(&mut self, index: usize, packet: Message) {
+        self.clients[index].client.as_mut()
+            .expect("client was dropped")
+            .send(&packet)
+            .unwrap();
+
+        self.settle();
+    }
+
+    // A chat line, or a command if it starts with '/'. The server fills in the sender.
+    pub fn say(&mut self, index: usize, channel: &str, line: &str) {
+        self.send(index, Message::chat(channel, "", line));
+    }
+
+    // Everything a client was sent since the last call
+    pub fn received(&mut self, index: usize) -> Vec<Message> {
+        self.clients[index].received.drain(..).collect()
+    }
+
+    // The client's end of the connection closes, like a client that quits without a word
+    pub fn drop_client(&mut self, index: usize) {
+        self.clients[index].client = None;
+        self.settle();
+    }
+
+    // Whether the server has closed the client's connection
+    pub fn is_disconnected(&self, index: usize) -> bool {
+        self.clients[index].is_disconnected
+    }
+
+    // Polls the server and every client in turn until a whole round goes by with nothing sent or received.
+    // Something written in one round is only read by the other end in the next, hence checking the queues too.
+    pub fn settle(&mut self) {
+        for _ in 0..MAX_ROUNDS {
+            let server: &Server = &self.chat.server;
+            let mut is_quiet: bool = server.connection_ids().iter().all(|id| server.queued_bytes(*id) == 0);
+
+            let events: Vec<NetworkEvent> = self.chat.server.poll(Some(Duration::from_secs(0))).unwrap();
+            is_quiet &= events.is_empty();
+
+            for event in events {
+                self.chat.handle_event(event);
+            }
+
+            self.chat.tick();
+
+            for sim in &mut self.clients {
+                let client: &mut Client = match sim.client {
+                    Some(ref mut client) => client,
+                    None => continue
+                };
+
+                is_quiet &= client.queued_bytes() == 0;
+
+                for event in client.poll(Some(Duration::from_secs(0))).unwrap() {
+                    is_quiet = false;
+
+                    match event {
+                        NetworkEvent::Message(_, packet, _) => sim.received.push(packet),
+                        NetworkEvent::Disconnected(_) => sim.is_disconnected = true,
+                        _ => ()
+                    }
+                }
+            }
+
+            if is_quiet {
+                return;
+            }
+        }
+
+        panic!("the chat never went quiet");
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.data_dir);
+    }
+}